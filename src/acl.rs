@@ -0,0 +1,95 @@
+use super::types::*;
+use bytes::Bytes;
+use std::convert::TryFrom;
+
+const ACL_HELP: &[&str] = &[
+	"GETUSER <username>",
+	"    Get the user's details.",
+	"LIST",
+	"    Show users details in config file format.",
+	"USERS",
+	"    List all the registered usernames.",
+	"WHOAMI",
+	"    Return the current connection username.",
+];
+
+/// The only user. There is no authentication, so every connection is
+/// `default` with full access.
+const DEFAULT_USER: &str = "default";
+
+/// `ACL <subcommand> [arg ...]`
+///
+/// Only the read-only subcommands clients use while connecting are
+/// supported, all describing the built-in `default` user.
+pub fn acl(a: &[RedisValue]) -> Result<ReturnValue, ReturnError> {
+	let subcommand = Bytes::try_from(a[1].clone())?;
+	let name = subcommand.to_ascii_lowercase();
+	let argc = match name.as_slice() {
+		b"getuser" => 3,
+		b"help" | b"list" | b"users" | b"whoami" => 2,
+		_ => return Ok(unknown_subcommand("ACL", &subcommand)),
+	};
+	if a.len() != argc {
+		return Ok(wrong_number_of_arguments(&format!(
+			"acl|{}",
+			String::from_utf8_lossy(&name)
+		)));
+	}
+	match name.as_slice() {
+		b"help" => Ok(subcommand_help("ACL", ACL_HELP)),
+		b"whoami" => Ok(ReturnValue::StringRes(Bytes::from(DEFAULT_USER))),
+		b"users" => Ok(ReturnValue::MultiStringRes(vec![Bytes::from(DEFAULT_USER)])),
+		b"list" => Ok(ReturnValue::MultiStringRes(vec![Bytes::from(format!(
+			"user {} on nopass ~* +@all",
+			DEFAULT_USER
+		))])),
+		_ => getuser(&a[2]),
+	}
+}
+
+/// `ACL GETUSER username`, in the shape Redis replies with.
+fn getuser(username: &RedisValue) -> Result<ReturnValue, ReturnError> {
+	if Bytes::try_from(username.clone())? != DEFAULT_USER.as_bytes() {
+		return Ok(ReturnValue::Nil);
+	}
+	let field = |name: &str| ReturnValue::StringRes(Bytes::from(name.to_string()));
+	Ok(ReturnValue::Array(vec![
+		field("flags"),
+		ReturnValue::MultiStringRes(vec![Bytes::from("on"), Bytes::from("nopass")]),
+		field("passwords"),
+		ReturnValue::Array(vec![]),
+		field("commands"),
+		field("+@all"),
+		field("keys"),
+		field("~*"),
+		field("channels"),
+		field("&*"),
+		field("selectors"),
+		ReturnValue::Array(vec![]),
+	]))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Session;
+
+	#[test]
+	fn whoami_is_the_default_user() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["ACL", "WHOAMI"]), b"$7\r\ndefault\r\n");
+		assert_eq!(session.run(&["acl", "whoami"]), b"$7\r\ndefault\r\n");
+		assert_eq!(
+			session.run(&["ACL", "WHOAMI", "extra"]),
+			&b"-ERR wrong number of arguments for 'acl|whoami' command\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn users_and_getuser_describe_default() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["ACL", "USERS"]), b"*1\r\n$7\r\ndefault\r\n");
+		assert_eq!(session.run(&["ACL", "GETUSER", "nobody"]), b"$-1\r\n");
+		let user = session.run(&["ACL", "GETUSER", "default"]);
+		assert!(user.starts_with(b"*12\r\n$5\r\nflags\r\n*2\r\n$2\r\non\r\n$6\r\nnopass\r\n"));
+	}
+}
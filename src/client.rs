@@ -0,0 +1,371 @@
+use super::types::*;
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+const CLIENT_HELP: &[&str] = &[
+	"GETNAME",
+	"    Return the name of the current connection.",
+	"ID",
+	"    Return the ID of the current connection.",
+	"KILL <ip:port>",
+	"    Kill connection made from <ip:port>.",
+	"KILL <option> <value> [<option> <value> [...]]",
+	"    Kill connections. Options are:",
+	"    * ADDR <ip:port>",
+	"      Kill connection made from <ip:port>",
+	"    * ID <client-id>",
+	"      Kill connections by client id.",
+	"    * SKIPME (YES|NO)",
+	"      Skip killing current connection (default: yes).",
+	"LIST",
+	"    Return information about client connections.",
+	"NO-EVICT (ON|OFF)",
+	"    Protect current client connection from eviction.",
+	"NO-TOUCH (ON|OFF)",
+	"    Will not touch LRU/LFU stats when this mode is on.",
+	"PAUSE <timeout> [WRITE|ALL]",
+	"    Suspend all, or just write, clients for <timeout> milliseconds.",
+	"SETNAME <name>",
+	"    Assign the name <name> to the current connection.",
+	"UNPAUSE",
+	"    Stop the current client pause, resuming traffic.",
+];
+
+/// `CLIENT <subcommand> [arg ...]`
+pub fn client(
+	a: &[RedisValue],
+	state: &mut State,
+	client: &mut Client,
+) -> Result<ReturnValue, ReturnError> {
+	let subcommand = Bytes::try_from(a[1].clone())?;
+	match subcommand.to_ascii_lowercase().as_slice() {
+		b"help" => Ok(subcommand_help("CLIENT", CLIENT_HELP)),
+		b"id" => {
+			if a.len() != 2 {
+				return Ok(wrong_number_of_arguments("client|id"));
+			}
+			Ok(ReturnValue::IntRes(client.id as i64))
+		}
+		b"kill" => kill(&a[2..], state, client),
+		b"getname" => {
+			if a.len() != 2 {
+				return Ok(wrong_number_of_arguments("client|getname"));
+			}
+			let clients = state.clients.lock().unwrap();
+			let name = clients.get(&client.id).map(|handle| handle.name.clone());
+			Ok(ReturnValue::StringRes(name.unwrap_or_default()))
+		}
+		b"setname" => {
+			if a.len() != 3 {
+				return Ok(wrong_number_of_arguments("client|setname"));
+			}
+			let name = Bytes::try_from(a[2].clone())?;
+			if name.iter().any(|c| !(b'!'..=b'~').contains(c)) {
+				return Ok(ReturnValue::Error(Bytes::from(
+					"ERR Client names cannot contain spaces, newlines or special characters.",
+				)));
+			}
+			if let Some(handle) = state.clients.lock().unwrap().get_mut(&client.id) {
+				handle.name = name;
+			}
+			Ok(ReturnValue::Ok)
+		}
+		b"list" => {
+			if a.len() != 2 {
+				return Ok(syntax_error());
+			}
+			Ok(ReturnValue::StringRes(Bytes::from(list(state))))
+		}
+		b"no-evict" => {
+			if a.len() != 3 {
+				return Ok(wrong_number_of_arguments("client|no-evict"));
+			}
+			match on_off(&a[2])? {
+				Some(on) => client.no_evict = on,
+				None => return Ok(syntax_error()),
+			}
+			Ok(ReturnValue::Ok)
+		}
+		b"no-touch" => {
+			if a.len() != 3 {
+				return Ok(wrong_number_of_arguments("client|no-touch"));
+			}
+			match on_off(&a[2])? {
+				Some(on) => client.no_touch = on,
+				None => return Ok(syntax_error()),
+			}
+			Ok(ReturnValue::Ok)
+		}
+		b"pause" => pause(a, state),
+		b"unpause" => {
+			if a.len() != 2 {
+				return Ok(wrong_number_of_arguments("client|unpause"));
+			}
+			state.unpause_clients();
+			Ok(ReturnValue::Ok)
+		}
+		_ => Ok(unknown_subcommand("CLIENT", &subcommand)),
+	}
+}
+
+/// The `ON|OFF` argument of `CLIENT NO-EVICT` and `CLIENT NO-TOUCH`;
+/// `None` for anything else.
+fn on_off(value: &RedisValue) -> Result<Option<bool>, ReturnError> {
+	Ok(match Bytes::try_from(value.clone())?.to_ascii_lowercase().as_slice() {
+		b"on" => Some(true),
+		b"off" => Some(false),
+		_ => None,
+	})
+}
+
+/// `CLIENT PAUSE timeout [WRITE | ALL]`. Paused commands wait in the
+/// dispatch path until the timeout passes, then run in order.
+fn pause(a: &[RedisValue], state: &State) -> Result<ReturnValue, ReturnError> {
+	if a.len() != 3 && a.len() != 4 {
+		return Ok(wrong_number_of_arguments("client|pause"));
+	}
+	let until = match integer_arg(&a[2]) {
+		Some(ms) if ms < 0 => return Ok(ReturnValue::Error(Bytes::from("ERR timeout is negative"))),
+		Some(ms) => Instant::now().checked_add(Duration::from_millis(ms as u64)),
+		None => None,
+	};
+	let until = match until {
+		Some(until) => until,
+		None => {
+			return Ok(ReturnValue::Error(Bytes::from(
+				"ERR timeout is not an integer or out of range",
+			)))
+		}
+	};
+	let writes_only = match a.get(3) {
+		None => false,
+		Some(mode) => match Bytes::try_from(mode.clone())?.to_ascii_lowercase().as_slice() {
+			b"all" => false,
+			b"write" => true,
+			_ => return Ok(syntax_error()),
+		},
+	};
+	state.pause_clients(until, writes_only);
+	Ok(ReturnValue::Ok)
+}
+
+/// One `CLIENT LIST` line per connection, in id order.
+fn list(state: &State) -> String {
+	let clients = state.clients.lock().unwrap();
+	let mut ids: Vec<&u64> = clients.keys().collect();
+	ids.sort();
+	let mut out = String::new();
+	for id in ids {
+		let handle = &clients[id];
+		let _ = writeln!(
+			out,
+			"id={} addr={} name={} age={}",
+			id,
+			handle.addr,
+			String::from_utf8_lossy(&handle.name),
+			handle.connected_at.elapsed().as_secs()
+		);
+	}
+	out
+}
+
+/// `CLIENT KILL <ip:port>` or `CLIENT KILL <filter> <value> ...`
+fn kill(args: &[RedisValue], state: &State, client: &Client) -> Result<ReturnValue, ReturnError> {
+	if args.is_empty() {
+		return Ok(wrong_number_of_arguments("client|kill"));
+	}
+
+	// The legacy form names a single address and may kill the caller.
+	if args.len() == 1 {
+		let addr = Bytes::try_from(args[0].clone())?;
+		let clients = state.clients.lock().unwrap();
+		return Ok(
+			match clients.values().find(|handle| handle.addr.as_bytes() == &addr[..]) {
+				Some(handle) => {
+					handle.kill();
+					ReturnValue::Ok
+				}
+				None => ReturnValue::Error(Bytes::from("ERR No such client")),
+			},
+		);
+	}
+	if !args.len().is_multiple_of(2) {
+		return Ok(syntax_error());
+	}
+
+	let (mut id, mut addr, mut skipme) = (None, None, true);
+	for pair in args.chunks(2) {
+		let option = Bytes::try_from(pair[0].clone())?;
+		match option.to_ascii_lowercase().as_slice() {
+			b"id" => match integer_arg(&pair[1]) {
+				Some(n) if n > 0 => id = Some(n as u64),
+				_ => {
+					return Ok(ReturnValue::Error(Bytes::from(
+						"ERR client-id should be greater than 0",
+					)))
+				}
+			},
+			b"addr" => addr = Some(Bytes::try_from(pair[1].clone())?),
+			b"skipme" => {
+				let value = Bytes::try_from(pair[1].clone())?;
+				skipme = match value.to_ascii_lowercase().as_slice() {
+					b"yes" => true,
+					b"no" => false,
+					_ => return Ok(syntax_error()),
+				};
+			}
+			_ => return Ok(syntax_error()),
+		}
+	}
+
+	let clients = state.clients.lock().unwrap();
+	let mut killed = 0;
+	for (&other, handle) in clients.iter() {
+		if id.is_some_and(|id| id != other)
+			|| addr.as_ref().is_some_and(|addr| handle.addr.as_bytes() != &addr[..])
+			|| (skipme && other == client.id)
+		{
+			continue;
+		}
+		handle.kill();
+		killed += 1;
+	}
+	Ok(ReturnValue::IntRes(killed))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::server::handle_client;
+	use crate::testing::{request, Session};
+	use std::io::{Read, Write};
+	use std::net::Shutdown;
+	use std::os::unix::net::UnixStream;
+	use std::sync::mpsc::channel;
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::Duration;
+
+	fn read_line(stream: &mut UnixStream) -> Vec<u8> {
+		let mut line = Vec::new();
+		let mut byte = [0];
+		while !line.ends_with(b"\r\n") {
+			stream.read_exact(&mut byte).unwrap();
+			line.push(byte[0]);
+		}
+		line
+	}
+
+	#[test]
+	fn kill_by_id_disconnects_that_client() {
+		let mut admin = Session::new();
+		let mut state = Arc::clone(&admin.state);
+		let (mut peer, server_end) = UnixStream::pair().unwrap();
+		let server = thread::spawn(move || {
+			let closer = server_end.try_clone().unwrap();
+			let shutdown = Box::new(move || {
+				let _ = closer.shutdown(Shutdown::Both);
+			});
+			handle_client(server_end, "unix".to_string(), shutdown, &mut state)
+		});
+
+		peer.write_all(&request(&[b"CLIENT", b"ID"])).unwrap();
+		let line = read_line(&mut peer);
+		let id = String::from_utf8(line[1..line.len() - 2].to_vec()).unwrap();
+
+		assert_eq!(admin.run(&["CLIENT", "KILL", "ID", "999999"]), b":0\r\n");
+		assert_eq!(admin.run(&["CLIENT", "KILL", "ID", &id]), b":1\r\n");
+		server.join().unwrap().unwrap();
+		let mut rest = Vec::new();
+		assert_eq!(peer.read_to_end(&mut rest).unwrap(), 0);
+		assert_eq!(admin.run(&["CLIENT", "KILL", "ID", &id]), b":0\r\n");
+	}
+
+	fn client_id(session: &mut Session) -> u64 {
+		let reply = session.run(&["CLIENT", "ID"]);
+		String::from_utf8_lossy(&reply[1..reply.len() - 2]).parse().unwrap()
+	}
+
+	#[test]
+	fn ids_increase_with_each_connection() {
+		let mut first = Session::new();
+		let mut previous = client_id(&mut first);
+		assert_eq!(client_id(&mut first), previous);
+		for _ in 0..3 {
+			let id = client_id(&mut first.join());
+			assert!(id > previous, "{} after {}", id, previous);
+			previous = id;
+		}
+	}
+
+	#[test]
+	fn getname_is_empty_until_setname() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["CLIENT", "GETNAME"]), b"$0\r\n\r\n");
+		assert_eq!(session.run(&["CLIENT", "SETNAME", "worker-1"]), b"+OK\r\n");
+		assert_eq!(session.run(&["CLIENT", "GETNAME"]), b"$8\r\nworker-1\r\n");
+		assert_eq!(session.join().run(&["CLIENT", "GETNAME"]), b"$0\r\n\r\n");
+		assert_eq!(
+			session.run(&["CLIENT", "SETNAME", "has space"]),
+			&b"-ERR Client names cannot contain spaces, newlines or special characters.\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn pause_all_delays_reads_until_unpaused() {
+		let mut admin = Session::new();
+		let mut reader = admin.join();
+		assert_eq!(admin.run(&["CLIENT", "PAUSE", "10000", "ALL"]), b"+OK\r\n");
+		let (sender, receiver) = channel();
+		let waiting = thread::spawn(move || sender.send(reader.run(&["GET", "k"])).unwrap());
+		assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+		assert_eq!(admin.run(&["CLIENT", "UNPAUSE"]), b"+OK\r\n");
+		assert_eq!(receiver.recv_timeout(Duration::from_secs(5)).unwrap(), b"$-1\r\n");
+		waiting.join().unwrap();
+	}
+
+	#[test]
+	fn pause_write_lets_reads_through() {
+		let mut admin = Session::new();
+		assert_eq!(admin.run(&["CLIENT", "PAUSE", "10000", "WRITE"]), b"+OK\r\n");
+		assert_eq!(admin.join().run(&["GET", "k"]), b"$-1\r\n");
+		assert_eq!(admin.run(&["CLIENT", "UNPAUSE"]), b"+OK\r\n");
+	}
+
+	#[test]
+	fn no_touch_leaves_the_idle_time_running() {
+		let mut session = Session::new();
+		session.run(&["SET", "k", "v"]);
+		assert_eq!(session.run(&["CLIENT", "NO-TOUCH", "on"]), b"+OK\r\n");
+		thread::sleep(Duration::from_millis(1100));
+		assert_eq!(session.run(&["GET", "k"]), b"$1\r\nv\r\n");
+		assert_eq!(session.run(&["OBJECT", "IDLETIME", "k"]), b":1\r\n");
+		assert_eq!(session.run(&["EXISTS", "k"]), b":1\r\n");
+		assert_eq!(session.run(&["OBJECT", "IDLETIME", "k"]), b":1\r\n");
+
+		// TOUCH still counts as an access, as in Redis.
+		session.run(&["TOUCH", "k"]);
+		assert_eq!(session.run(&["OBJECT", "IDLETIME", "k"]), b":0\r\n");
+
+		assert_eq!(session.run(&["CLIENT", "NO-TOUCH", "off"]), b"+OK\r\n");
+		thread::sleep(Duration::from_millis(1100));
+		session.run(&["GET", "k"]);
+		assert_eq!(session.run(&["OBJECT", "IDLETIME", "k"]), b":0\r\n");
+	}
+
+	#[test]
+	fn no_evict_and_no_touch_take_on_or_off() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["CLIENT", "NO-EVICT", "ON"]), b"+OK\r\n");
+		assert!(session.client.no_evict);
+		assert_eq!(session.run(&["CLIENT", "NO-EVICT", "off"]), b"+OK\r\n");
+		assert!(!session.client.no_evict);
+		assert_eq!(session.run(&["CLIENT", "NO-TOUCH", "maybe"]), b"-ERR syntax error\r\n");
+		assert!(!session.client.no_touch);
+		assert_eq!(
+			session.run(&["CLIENT", "NO-TOUCH"]),
+			&b"-ERR wrong number of arguments for 'client|no-touch' command\r\n"[..]
+		);
+	}
+}
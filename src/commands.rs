@@ -0,0 +1,871 @@
+use super::types::*;
+use bytes::Bytes;
+use std::convert::TryFrom;
+
+/// Static metadata for a supported command.
+///
+/// Mirrors the rows Redis returns from `COMMAND`, plus the few
+/// documentation fields needed for `COMMAND DOCS`.
+pub struct CommandSpec {
+	pub name: &'static str,
+	/// Positive for an exact argument count (including the command name),
+	/// negative for a minimum.
+	pub arity: i64,
+	pub flags: &'static [&'static str],
+	pub first_key: i64,
+	pub last_key: i64,
+	pub step: i64,
+	pub summary: &'static str,
+	pub since: &'static str,
+	pub group: &'static str,
+}
+
+pub static COMMAND_TABLE: &[CommandSpec] = &[
+	CommandSpec {
+		name: "acl",
+		arity: -2,
+		flags: &["admin", "noscript", "loading", "stale"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "A container for Access List Control commands.",
+		since: "6.0.0",
+		group: "server",
+	},
+	CommandSpec {
+		name: "append",
+		arity: 3,
+		flags: &["write", "denyoom", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+		since: "2.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "asking",
+		arity: 1,
+		flags: &["fast"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Signals that a cluster client is following an -ASK redirect.",
+		since: "3.0.0",
+		group: "cluster",
+	},
+	CommandSpec {
+		name: "bitfield",
+		arity: -2,
+		flags: &["write", "denyoom"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Performs arbitrary bitfield integer operations on strings.",
+		since: "3.2.0",
+		group: "bitmap",
+	},
+	CommandSpec {
+		name: "bitfield_ro",
+		arity: -2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Performs arbitrary read-only bitfield integer operations on strings.",
+		since: "6.0.0",
+		group: "bitmap",
+	},
+	CommandSpec {
+		name: "bitpos",
+		arity: -3,
+		flags: &["readonly"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Finds the first set (1) or clear (0) bit in a string.",
+		since: "2.8.7",
+		group: "bitmap",
+	},
+	CommandSpec {
+		name: "client",
+		arity: -2,
+		flags: &["admin", "noscript", "loading", "stale"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "A container for client connection commands.",
+		since: "2.4.0",
+		group: "connection",
+	},
+	CommandSpec {
+		name: "command",
+		arity: -1,
+		flags: &["loading", "stale"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Returns detailed information about all commands.",
+		since: "2.8.13",
+		group: "server",
+	},
+	CommandSpec {
+		name: "dbsize",
+		arity: 1,
+		flags: &["readonly", "fast"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Returns the number of keys in the database.",
+		since: "1.0.0",
+		group: "server",
+	},
+	CommandSpec {
+		name: "debug",
+		arity: -2,
+		flags: &["admin", "noscript", "loading", "stale"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "A container for debugging commands.",
+		since: "1.0.0",
+		group: "server",
+	},
+	CommandSpec {
+		name: "decr",
+		arity: 2,
+		flags: &["write", "denyoom", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+		since: "1.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "decrby",
+		arity: 3,
+		flags: &["write", "denyoom", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+		since: "1.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "del",
+		arity: -2,
+		flags: &["write"],
+		first_key: 1,
+		last_key: -1,
+		step: 1,
+		summary: "Deletes one or more keys.",
+		since: "1.0.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "echo",
+		arity: 2,
+		flags: &["fast"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Returns the given string.",
+		since: "1.0.0",
+		group: "connection",
+	},
+	CommandSpec {
+		name: "exists",
+		arity: -2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: -1,
+		step: 1,
+		summary: "Determines whether one or more keys exist.",
+		since: "1.0.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "expire",
+		arity: 3,
+		flags: &["write", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Sets the expiration time of a key in seconds.",
+		since: "1.0.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "expireat",
+		arity: 3,
+		flags: &["write", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Sets the expiration time of a key to a Unix timestamp.",
+		since: "1.2.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "expiretime",
+		arity: 2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns the expiration time of a key as a Unix timestamp.",
+		since: "7.0.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "failover",
+		arity: -1,
+		flags: &["admin", "noscript", "stale"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Starts a coordinated failover from a server to one of its replicas.",
+		since: "6.2.0",
+		group: "server",
+	},
+	CommandSpec {
+		name: "get",
+		arity: 2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns the string value of a key.",
+		since: "1.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "getdel",
+		arity: 2,
+		flags: &["write", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns the string value of a key after deleting the key.",
+		since: "6.2.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "getex",
+		arity: -2,
+		flags: &["write", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns the string value of a key after setting its expiration time. Deletes the key if the expiration time is in the past.",
+		since: "6.2.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "getrange",
+		arity: 4,
+		flags: &["readonly"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns a substring of the string stored at a key.",
+		since: "2.4.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "incr",
+		arity: 2,
+		flags: &["write", "denyoom", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+		since: "1.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "incrby",
+		arity: 3,
+		flags: &["write", "denyoom", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+		since: "1.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "info",
+		arity: -1,
+		flags: &["loading", "stale"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Returns information and statistics about the server.",
+		since: "1.0.0",
+		group: "server",
+	},
+	CommandSpec {
+		name: "lcs",
+		arity: -3,
+		flags: &["readonly"],
+		first_key: 1,
+		last_key: 2,
+		step: 1,
+		summary: "Finds the longest common substring.",
+		since: "7.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "memory",
+		arity: -2,
+		flags: &[],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "A container for memory diagnostics commands.",
+		since: "4.0.0",
+		group: "server",
+	},
+	CommandSpec {
+		name: "mget",
+		arity: -2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: -1,
+		step: 1,
+		summary: "Atomically returns the string values of one or more keys.",
+		since: "1.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "mset",
+		arity: -3,
+		flags: &["write", "denyoom"],
+		first_key: 1,
+		last_key: -1,
+		step: 2,
+		summary: "Atomically creates or modifies the string values of one or more keys.",
+		since: "1.0.1",
+		group: "string",
+	},
+	CommandSpec {
+		name: "object",
+		arity: -2,
+		flags: &[],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "A container for object introspection commands.",
+		since: "2.2.3",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "pexpire",
+		arity: 3,
+		flags: &["write", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Sets the expiration time of a key in milliseconds.",
+		since: "2.6.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "pexpireat",
+		arity: 3,
+		flags: &["write", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+		since: "2.6.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "pexpiretime",
+		arity: 2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns the expiration time of a key as a Unix milliseconds timestamp.",
+		since: "7.0.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "ping",
+		arity: -1,
+		flags: &["stale", "fast"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Returns the server's liveliness response.",
+		since: "1.0.0",
+		group: "connection",
+	},
+	CommandSpec {
+		name: "psetex",
+		arity: 4,
+		flags: &["write", "denyoom"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Sets both string value and expiration time in milliseconds of a key. The key is created if it doesn't exist.",
+		since: "2.6.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "pttl",
+		arity: 2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns the expiration time in milliseconds of a key.",
+		since: "2.6.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "readonly",
+		arity: 1,
+		flags: &["loading", "stale", "fast"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Enables read-only queries for a connection to a Redis Cluster replica node.",
+		since: "3.0.0",
+		group: "cluster",
+	},
+	CommandSpec {
+		name: "readwrite",
+		arity: 1,
+		flags: &["loading", "stale", "fast"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Enables read-write queries for a connection to a Redis Cluster replica node.",
+		since: "3.0.0",
+		group: "cluster",
+	},
+	CommandSpec {
+		name: "replicaof",
+		arity: 3,
+		flags: &["admin", "noscript", "stale"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Configures a server as replica of another, or promotes it to a master.",
+		since: "5.0.0",
+		group: "server",
+	},
+	CommandSpec {
+		name: "set",
+		arity: -3,
+		flags: &["write", "denyoom", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Sets the string value of a key.",
+		since: "1.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "setex",
+		arity: 4,
+		flags: &["write", "denyoom"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Sets the string value and expiration time of a key. Creates the key if it doesn't exist.",
+		since: "2.0.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "setrange",
+		arity: 4,
+		flags: &["write", "denyoom"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.",
+		since: "2.2.0",
+		group: "string",
+	},
+	CommandSpec {
+		name: "slaveof",
+		arity: 3,
+		flags: &["admin", "noscript", "stale"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "Sets a Redis server as a replica of another, or promotes it to being a master.",
+		since: "1.0.0",
+		group: "server",
+	},
+	CommandSpec {
+		name: "slowlog",
+		arity: -2,
+		flags: &["admin", "loading", "stale"],
+		first_key: 0,
+		last_key: 0,
+		step: 0,
+		summary: "A container for slow log commands.",
+		since: "2.2.12",
+		group: "server",
+	},
+	CommandSpec {
+		name: "touch",
+		arity: -2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: -1,
+		step: 1,
+		summary: "Returns the number of existing keys out of those specified after updating the time they were last accessed.",
+		since: "3.2.1",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "ttl",
+		arity: 2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns the expiration time in seconds of a key.",
+		since: "1.0.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "type",
+		arity: 2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Determines the type of value stored at a key.",
+		since: "1.0.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "unlink",
+		arity: -2,
+		flags: &["write", "fast"],
+		first_key: 1,
+		last_key: -1,
+		step: 1,
+		summary: "Asynchronously deletes one or more keys.",
+		since: "4.0.0",
+		group: "generic",
+	},
+	CommandSpec {
+		name: "xadd",
+		arity: -5,
+		flags: &["write", "denyoom", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Appends a new message to a stream. Creates the key if it doesn't exist.",
+		since: "5.0.0",
+		group: "stream",
+	},
+	CommandSpec {
+		name: "xlen",
+		arity: 2,
+		flags: &["readonly", "fast"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Return the number of messages in a stream.",
+		since: "5.0.0",
+		group: "stream",
+	},
+	CommandSpec {
+		name: "xrange",
+		arity: -4,
+		flags: &["readonly"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns the messages from a stream within a range of IDs.",
+		since: "5.0.0",
+		group: "stream",
+	},
+	CommandSpec {
+		name: "xrevrange",
+		arity: -4,
+		flags: &["readonly"],
+		first_key: 1,
+		last_key: 1,
+		step: 1,
+		summary: "Returns the messages from a stream within a range of IDs in reverse order.",
+		since: "5.0.0",
+		group: "stream",
+	},
+];
+
+/// Case-insensitive lookup into the command table.
+pub fn lookup_command(name: &str) -> Option<&'static CommandSpec> {
+	COMMAND_TABLE
+		.iter()
+		.find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+fn bulk(s: &'static str) -> ReturnValue {
+	ReturnValue::StringRes(Bytes::from(s))
+}
+
+impl CommandSpec {
+	/// Whether the command may modify the keyspace, and so is refused in
+	/// read-only mode.
+	pub fn is_write(&self) -> bool {
+		self.flags.contains(&"write")
+	}
+
+	/// The `COMMAND INFO` row: name, arity, flags, first key, last key, step.
+	pub fn info(&self) -> ReturnValue {
+		let flags = self
+			.flags
+			.iter()
+			.map(|flag| ReturnValue::SimpleString(Bytes::from(*flag)))
+			.collect();
+		ReturnValue::Array(vec![
+			bulk(self.name),
+			ReturnValue::IntRes(self.arity),
+			ReturnValue::Array(flags),
+			ReturnValue::IntRes(self.first_key),
+			ReturnValue::IntRes(self.last_key),
+			ReturnValue::IntRes(self.step),
+		])
+	}
+
+	/// Whether `argc` arguments (including the command name) satisfy the
+	/// command's arity.
+	pub fn accepts_arity(&self, argc: usize) -> bool {
+		let argc = argc as i64;
+		if self.arity < 0 {
+			argc >= -self.arity
+		} else {
+			argc == self.arity
+		}
+	}
+
+	/// The key arguments of a full invocation, following the first key, last
+	/// key and step of the spec. A negative last key counts from the end.
+	pub fn keys<'a>(&self, args: &'a [RedisValue]) -> Vec<&'a RedisValue> {
+		if self.first_key <= 0 || self.step <= 0 {
+			return Vec::new();
+		}
+		let last = if self.last_key < 0 {
+			args.len() as i64 + self.last_key
+		} else {
+			self.last_key.min(args.len() as i64 - 1)
+		};
+		let mut keys = Vec::new();
+		let mut i = self.first_key;
+		while i <= last {
+			keys.push(&args[i as usize]);
+			i += self.step;
+		}
+		keys
+	}
+
+	/// The `COMMAND DOCS` map for this command, flattened for RESP2.
+	pub fn docs(&self) -> ReturnValue {
+		ReturnValue::Array(vec![
+			bulk("summary"),
+			bulk(self.summary),
+			bulk("since"),
+			bulk(self.since),
+			bulk("group"),
+			bulk(self.group),
+		])
+	}
+}
+
+fn error(msg: &'static str) -> ReturnValue {
+	ReturnValue::Error(Bytes::from(msg))
+}
+
+fn arg_string(value: &RedisValue) -> Result<String, ReturnError> {
+	let b = Bytes::try_from(value.clone())?;
+	Ok(String::from_utf8_lossy(&b).to_string())
+}
+
+const COMMAND_HELP: &[&str] = &[
+	"(no subcommand)",
+	"    Return details about all commands.",
+	"COUNT",
+	"    Return the total number of commands in this server.",
+	"DOCS [<command-name> ...]",
+	"    Return documentation details about multiple commands.",
+	"    If no command names are given, documentation details for all",
+	"    commands are returned.",
+	"GETKEYS <full-command>",
+	"    Return the keys from a full command.",
+	"INFO [<command-name> ...]",
+	"    Return details about multiple commands.",
+	"    If no command names are given, details for all commands are returned.",
+];
+
+/// Handles `COMMAND [COUNT | INFO [cmd...] | DOCS [cmd...] | GETKEYS cmd [arg...]]`.
+pub fn command(args: &[RedisValue]) -> Result<ReturnValue, ReturnError> {
+	let subcommand = match args.first() {
+		Some(value) => arg_string(value)?.to_lowercase(),
+		None => {
+			let rows = COMMAND_TABLE.iter().map(CommandSpec::info).collect();
+			return Ok(ReturnValue::Array(rows));
+		}
+	};
+	let names = &args[1..];
+
+	match subcommand.as_str() {
+		"help" => Ok(subcommand_help("COMMAND", COMMAND_HELP)),
+		"count" => Ok(ReturnValue::IntRes(COMMAND_TABLE.len() as i64)),
+		"info" => {
+			if names.is_empty() {
+				let rows = COMMAND_TABLE.iter().map(CommandSpec::info).collect();
+				return Ok(ReturnValue::Array(rows));
+			}
+			let mut rows = Vec::with_capacity(names.len());
+			for name in names {
+				match lookup_command(&arg_string(name)?) {
+					Some(spec) => rows.push(spec.info()),
+					None => rows.push(ReturnValue::Nil),
+				}
+			}
+			Ok(ReturnValue::Array(rows))
+		}
+		"docs" => {
+			let specs: Vec<&CommandSpec> = if names.is_empty() {
+				COMMAND_TABLE.iter().collect()
+			} else {
+				let mut specs = Vec::with_capacity(names.len());
+				for name in names {
+					if let Some(spec) = lookup_command(&arg_string(name)?) {
+						specs.push(spec);
+					}
+				}
+				specs
+			};
+			let mut docs = Vec::with_capacity(specs.len() * 2);
+			for spec in specs {
+				docs.push(bulk(spec.name));
+				docs.push(spec.docs());
+			}
+			Ok(ReturnValue::Array(docs))
+		}
+		"getkeys" => {
+			let spec = match names.first() {
+				Some(name) => match lookup_command(&arg_string(name)?) {
+					Some(spec) => spec,
+					None => return Ok(error("ERR Invalid command specified")),
+				},
+				None => return Ok(wrong_number_of_arguments("command|getkeys")),
+			};
+			if !spec.accepts_arity(names.len()) {
+				return Ok(error(
+					"ERR Invalid number of arguments specified for command",
+				));
+			}
+			let keys = spec.keys(names);
+			if keys.is_empty() {
+				return Ok(error("ERR The command has no key arguments"));
+			}
+			let mut values = Vec::with_capacity(keys.len());
+			for key in keys {
+				values.push(Bytes::try_from(key.clone())?);
+			}
+			Ok(ReturnValue::MultiStringRes(values))
+		}
+		other => Ok(unknown_subcommand("COMMAND", other.as_bytes())),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Session;
+
+	#[test]
+	fn command_info_row_starts_with_the_name() {
+		let mut session = Session::new();
+		let reply = session.run(&["COMMAND", "INFO", "get"]);
+		assert!(reply.starts_with(b"*1\r\n*6\r\n$3\r\nget\r\n:2\r\n"));
+	}
+
+	#[test]
+	fn command_info_unknown_command_is_nil() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["COMMAND", "INFO", "nosuch"]), b"*1\r\n$-1\r\n");
+	}
+
+	#[test]
+	fn command_docs_is_keyed_by_name() {
+		let mut session = Session::new();
+		let reply = session.run(&["COMMAND", "DOCS", "get"]);
+		assert!(reply.starts_with(b"*2\r\n$3\r\nget\r\n"));
+	}
+
+	#[test]
+	fn command_count_matches_the_table() {
+		let mut session = Session::new();
+		let expected = format!(":{}\r\n", super::COMMAND_TABLE.len());
+		assert_eq!(session.run(&["COMMAND", "COUNT"]), expected.as_bytes());
+	}
+
+	#[test]
+	fn command_getkeys_single_key() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["COMMAND", "GETKEYS", "GET", "k"]), b"*1\r\n$1\r\nk\r\n");
+	}
+
+	#[test]
+	fn command_getkeys_every_argument_is_a_key() {
+		let mut session = Session::new();
+		assert_eq!(
+			session.run(&["COMMAND", "GETKEYS", "MGET", "a", "b", "c"]),
+			&b"*3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn command_getkeys_steps_over_values() {
+		let mut session = Session::new();
+		assert_eq!(
+			session.run(&["COMMAND", "GETKEYS", "MSET", "a", "1", "b", "2"]),
+			&b"*2\r\n$1\r\na\r\n$1\r\nb\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn command_getkeys_rejects_bad_calls() {
+		let mut session = Session::new();
+		assert_eq!(
+			session.run(&["COMMAND", "GETKEYS", "GET"]),
+			&b"-ERR Invalid number of arguments specified for command\r\n"[..]
+		);
+		assert_eq!(
+			session.run(&["COMMAND", "GETKEYS", "PING"]),
+			&b"-ERR The command has no key arguments\r\n"[..]
+		);
+	}
+
+	fn is_fast(session: &mut Session, name: &str) -> bool {
+		let reply = session.run(&["COMMAND", "INFO", name]);
+		assert!(reply.starts_with(b"*1\r\n*6\r\n"), "{}", name);
+		reply.windows(7).any(|window| window == b"+fast\r\n")
+	}
+
+	#[test]
+	fn only_constant_time_commands_are_fast() {
+		let mut session = Session::new();
+		for name in &["get", "set", "incr", "ttl"] {
+			assert!(is_fast(&mut session, name), "{} should be fast", name);
+		}
+		for name in &["setex", "psetex", "lcs", "mset"] {
+			assert!(!is_fast(&mut session, name), "{} should not be fast", name);
+		}
+	}
+}
@@ -0,0 +1,228 @@
+use super::stream::Stream;
+use super::types::ReturnError;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Milliseconds since the Unix epoch, the unit expiry deadlines are kept in.
+pub fn now_ms() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_millis() as u64)
+		.unwrap_or(0)
+}
+
+/// A stored value. A command given a key holding a different type fails
+/// with `ReturnError::WrongType`.
+pub enum Value {
+	String(Bytes),
+	/// A string holding the canonical decimal form of an `i64`, kept as the
+	/// number so `INCR` and friends needn't parse and format it each time.
+	Int(i64),
+	Stream(Stream),
+}
+
+impl Value {
+	/// The name `TYPE` reports.
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Value::String(_) | Value::Int(_) => "string",
+			Value::Stream(_) => "stream",
+		}
+	}
+}
+
+impl From<Bytes> for Value {
+	fn from(value: Bytes) -> Value {
+		Value::String(value)
+	}
+}
+
+impl From<Stream> for Value {
+	fn from(value: Stream) -> Value {
+		Value::Stream(value)
+	}
+}
+
+/// The keyspace: values plus, for keys that have one, an expiry deadline.
+///
+/// A key whose deadline has passed is removed the first time it's looked
+/// up, so every read sees it as absent. `active_expire` also removes such
+/// keys in the background, for those nobody asks for again.
+#[derive(Default)]
+pub struct Db {
+	entries: HashMap<Bytes, Entry>,
+	/// Deadlines in Unix milliseconds, kept apart from the values as in
+	/// Redis so keys without a TTL cost nothing extra.
+	expires: HashMap<Bytes, u64>,
+	/// Where in `expires` the next `active_expire` call starts.
+	expire_cursor: usize,
+}
+
+/// A value and when it was last accessed, for `OBJECT IDLETIME`.
+struct Entry {
+	value: Value,
+	/// Unix milliseconds, set on write and by `touch`.
+	accessed: u64,
+}
+
+impl Entry {
+	fn new(value: Value) -> Entry {
+		Entry { value, accessed: now_ms() }
+	}
+}
+
+impl Db {
+	pub fn new() -> Db {
+		Db::default()
+	}
+
+	/// Drop `key` if its deadline has passed, reporting whether it did.
+	fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+		match self.expires.get(key) {
+			Some(&deadline) if deadline <= now_ms() => {
+				self.expires.remove(key);
+				self.entries.remove(key);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	pub fn get_value(&mut self, key: &[u8]) -> Option<&Value> {
+		self.expire_if_needed(key);
+		self.entries.get(key).map(|entry| &entry.value)
+	}
+
+	pub fn get_value_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+		self.expire_if_needed(key);
+		self.entries.get_mut(key).map(|entry| &mut entry.value)
+	}
+
+	/// The string at `key`. Integers are formatted on the way out.
+	pub fn get(&mut self, key: &[u8]) -> Result<Option<Bytes>, ReturnError> {
+		match self.get_value(key) {
+			Some(Value::String(value)) => Ok(Some(value.clone())),
+			Some(Value::Int(n)) => Ok(Some(Bytes::from(n.to_string()))),
+			Some(_) => Err(ReturnError::WrongType),
+			None => Ok(None),
+		}
+	}
+
+	pub fn get_stream(&mut self, key: &[u8]) -> Result<Option<&Stream>, ReturnError> {
+		match self.get_value(key) {
+			Some(Value::Stream(stream)) => Ok(Some(stream)),
+			Some(_) => Err(ReturnError::WrongType),
+			None => Ok(None),
+		}
+	}
+
+	pub fn get_stream_mut(&mut self, key: &[u8]) -> Result<Option<&mut Stream>, ReturnError> {
+		match self.get_value_mut(key) {
+			Some(Value::Stream(stream)) => Ok(Some(stream)),
+			Some(_) => Err(ReturnError::WrongType),
+			None => Ok(None),
+		}
+	}
+
+	pub fn contains_key(&mut self, key: &[u8]) -> bool {
+		self.get_value(key).is_some()
+	}
+
+	/// Store `value`, discarding any TTL the key had, as `SET` does.
+	pub fn insert(&mut self, key: Bytes, value: impl Into<Value>) -> Option<Value> {
+		self.expire_if_needed(&key);
+		self.expires.remove(&key);
+		self.entries.insert(key, Entry::new(value.into())).map(|entry| entry.value)
+	}
+
+	/// Store `value`, keeping any TTL, for commands that modify a value in
+	/// place such as `SETRANGE`.
+	pub fn update(&mut self, key: Bytes, value: impl Into<Value>) -> Option<Value> {
+		self.expire_if_needed(&key);
+		self.entries.insert(key, Entry::new(value.into())).map(|entry| entry.value)
+	}
+
+	pub fn remove(&mut self, key: &[u8]) -> Option<Value> {
+		if self.expire_if_needed(key) {
+			return None;
+		}
+		self.expires.remove(key);
+		self.entries.remove(key).map(|entry| entry.value)
+	}
+
+	/// Set the deadline of an existing key. Returns false if there is no
+	/// such key.
+	pub fn set_expiry(&mut self, key: &[u8], deadline: u64) -> bool {
+		if !self.contains_key(key) {
+			return false;
+		}
+		self.expires.insert(Bytes::copy_from_slice(key), deadline);
+		true
+	}
+
+	/// Clear any TTL on `key`, leaving its value.
+	pub fn persist(&mut self, key: &[u8]) {
+		self.expire_if_needed(key);
+		self.expires.remove(key);
+	}
+
+	/// The deadline of `key`, or `None` if it has no TTL (or doesn't exist).
+	pub fn expiry(&mut self, key: &[u8]) -> Option<u64> {
+		self.expire_if_needed(key);
+		self.expires.get(key).copied()
+	}
+
+	/// Mark `key` as accessed now. Done once per command for the keys it
+	/// names, rather than by every lookup, so a connection can opt out.
+	pub fn touch(&mut self, key: &[u8]) {
+		self.expire_if_needed(key);
+		if let Some(entry) = self.entries.get_mut(key) {
+			entry.accessed = now_ms();
+		}
+	}
+
+	/// Milliseconds since `key` was last accessed, or `None` if it doesn't
+	/// exist.
+	pub fn idle_time(&mut self, key: &[u8]) -> Option<u64> {
+		self.expire_if_needed(key);
+		let entry = self.entries.get(key)?;
+		Some(now_ms().saturating_sub(entry.accessed))
+	}
+
+	/// Check the next `count` keys with a TTL, resuming where the previous
+	/// call stopped, and remove those that have expired. Returns how many
+	/// were removed.
+	pub fn active_expire(&mut self, count: usize) -> usize {
+		if self.expire_cursor >= self.expires.len() {
+			self.expire_cursor = 0;
+		}
+		let now = now_ms();
+		let expired: Vec<Bytes> = self
+			.expires
+			.iter()
+			.skip(self.expire_cursor)
+			.take(count)
+			.filter(|&(_, &deadline)| deadline <= now)
+			.map(|(key, _)| key.clone())
+			.collect();
+		// Removed keys no longer take up a place in the table.
+		self.expire_cursor += count - expired.len();
+		for key in &expired {
+			self.expires.remove(key);
+			self.entries.remove(key);
+		}
+		expired.len()
+	}
+
+	/// Number of stored keys. As in Redis, this includes expired keys that
+	/// haven't been looked up or swept yet, so it stays O(1).
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	#[allow(dead_code)]
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+}
@@ -0,0 +1,177 @@
+use super::glob::glob_match;
+use super::replication::generate_id;
+use super::types::*;
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+const DEBUG_HELP: &[&str] = &[
+	"CHANGE-REPL-ID",
+	"    Change the replication IDs of the instance.",
+	"    Dangerous: should be used only for testing the replication subsystem.",
+	"SET-ACTIVE-EXPIRE <0|1>",
+	"    Setting it to 0 disables expiring keys in background when they are not",
+	"    accessed (otherwise the Redis behavior). Setting it to 1 reenables back the",
+	"    default.",
+	"SLEEP <seconds>",
+	"    Stop the server for <seconds>. Decimals allowed.",
+	"STRINGMATCH-LEN <pattern> <string>",
+	"    Run the glob matcher against <string>. Returns 1 on a match, 0 otherwise.",
+];
+
+/// `DEBUG <subcommand> [arg ...]`
+pub fn debug(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let subcommand = Bytes::try_from(a[1].clone())?;
+	match subcommand.to_ascii_lowercase().as_slice() {
+		b"help" => Ok(subcommand_help("DEBUG", DEBUG_HELP)),
+		b"change-repl-id" => {
+			*state.master_replid.lock().unwrap() = generate_id();
+			Ok(ReturnValue::Ok)
+		}
+		b"set-active-expire" => {
+			if a.len() != 3 {
+				return Ok(wrong_number_of_arguments("debug|set-active-expire"));
+			}
+			match integer_arg(&a[2]) {
+				Some(enabled) => {
+					state.active_expire.store(enabled != 0, Ordering::Relaxed);
+					Ok(ReturnValue::Ok)
+				}
+				None => Ok(not_an_integer()),
+			}
+		}
+		b"sleep" => {
+			if a.len() != 3 {
+				return Ok(wrong_number_of_arguments("debug|sleep"));
+			}
+			let seconds = Bytes::try_from(a[2].clone())?;
+			// Negative, non-finite and out of range values are all refused
+			// by `try_from_secs_f64`.
+			let duration = std::str::from_utf8(&seconds)
+				.ok()
+				.and_then(|s| s.parse::<f64>().ok())
+				.and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+			match duration {
+				Some(duration) => {
+					thread::sleep(duration);
+					Ok(ReturnValue::Ok)
+				}
+				None => Ok(ReturnValue::Error(Bytes::from("ERR value is not a valid float"))),
+			}
+		}
+		// Exposes the glob matcher directly, independent of any keyspace
+		// command, so its edge cases can be checked from a client.
+		b"stringmatch-len" => {
+			if a.len() != 4 {
+				return Ok(wrong_number_of_arguments("debug|stringmatch-len"));
+			}
+			let pattern = Bytes::try_from(a[2].clone())?;
+			let string = Bytes::try_from(a[3].clone())?;
+			Ok(ReturnValue::IntRes(glob_match(&pattern, &string) as i64))
+		}
+		_ => Ok(unknown_subcommand("DEBUG", &subcommand)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::{request, Session};
+	use std::time::{Duration, Instant};
+
+	fn matches(session: &mut Session, pattern: &str, string: &str) -> bool {
+		match &session.run(&["DEBUG", "STRINGMATCH-LEN", pattern, string])[..] {
+			b":1\r\n" => true,
+			b":0\r\n" => false,
+			other => panic!("unexpected reply {:?}", String::from_utf8_lossy(other)),
+		}
+	}
+
+	#[test]
+	fn stringmatch_len_nested_wildcards() {
+		let mut session = Session::new();
+		assert!(matches(&mut session, "*?*?*", "ab"));
+		assert!(!matches(&mut session, "*?*?*", "a"));
+		assert!(matches(&mut session, "a*b*c", "aXXbYYc"));
+		assert!(!matches(&mut session, "a*b*c", "aXXcYYb"));
+		assert!(matches(&mut session, "h?llo*[0-9]", "hello world 7"));
+		assert!(matches(&mut session, "[^a]*", "bcd"));
+		assert!(!matches(&mut session, "[^a]*", "abc"));
+	}
+
+	#[test]
+	fn stringmatch_len_escaped_metacharacters() {
+		let mut session = Session::new();
+		assert!(matches(&mut session, "a\\*b", "a*b"));
+		assert!(!matches(&mut session, "a\\*b", "aXb"));
+		assert!(matches(&mut session, "\\?", "?"));
+		assert!(!matches(&mut session, "\\?", "x"));
+		assert!(matches(&mut session, "[\\]]", "]"));
+	}
+
+	#[test]
+	fn stringmatch_len_pathological_pattern_finishes() {
+		let mut session = Session::new();
+		let pattern = "*a".repeat(30) + "b";
+		let string = "a".repeat(60);
+		assert!(!matches(&mut session, &pattern, &string));
+	}
+
+	#[test]
+	fn sleep_keeps_pipelined_replies_in_order() {
+		let mut session = Session::new();
+		let mut input = request(&[b"PING"]);
+		input.extend(request(&[b"DEBUG", b"SLEEP", b"0.1"]));
+		input.extend(request(&[b"PING"]));
+		let started = Instant::now();
+		assert_eq!(session.send(&input), b"+PONG\r\n+OK\r\n+PONG\r\n");
+		assert!(started.elapsed() >= Duration::from_millis(100));
+	}
+
+	#[test]
+	fn sleep_rejects_unrepresentable_durations() {
+		let mut session = Session::new();
+		for seconds in &["1e20", "-1", "inf", "nan", "soon"] {
+			assert_eq!(
+				session.run(&["DEBUG", "SLEEP", seconds]),
+				&b"-ERR value is not a valid float\r\n"[..],
+				"{}",
+				seconds
+			);
+		}
+		assert_eq!(session.run(&["DEBUG", "SLEEP", "0"]), b"+OK\r\n");
+	}
+
+	#[test]
+	fn set_active_expire_leaves_expired_keys_to_lazy_expiry() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["DEBUG", "SET-ACTIVE-EXPIRE", "0"]), b"+OK\r\n");
+		session.run(&["SET", "k", "v", "PX", "10"]);
+		std::thread::sleep(Duration::from_millis(20));
+		session.state.active_expire_cycle();
+		assert_eq!(session.run(&["DBSIZE"]), b":1\r\n");
+		assert_eq!(session.run(&["GET", "k"]), b"$-1\r\n");
+		assert_eq!(session.run(&["DBSIZE"]), b":0\r\n");
+
+		assert_eq!(session.run(&["DEBUG", "SET-ACTIVE-EXPIRE", "1"]), b"+OK\r\n");
+		session.run(&["SET", "k", "v", "PX", "10"]);
+		session.run(&["SET", "kept", "v"]);
+		std::thread::sleep(Duration::from_millis(20));
+		session.state.active_expire_cycle();
+		assert_eq!(session.run(&["DBSIZE"]), b":1\r\n");
+	}
+
+	#[test]
+	fn set_active_expire_wants_an_integer() {
+		let mut session = Session::new();
+		assert_eq!(
+			session.run(&["DEBUG", "SET-ACTIVE-EXPIRE", "yes"]),
+			&b"-ERR value is not an integer or out of range\r\n"[..]
+		);
+		assert_eq!(
+			session.run(&["DEBUG", "SET-ACTIVE-EXPIRE"]),
+			&b"-ERR wrong number of arguments for 'debug|set-active-expire' command\r\n"[..]
+		);
+	}
+}
@@ -0,0 +1,91 @@
+/// Match `string` against a Redis glob `pattern`.
+///
+/// Supports `*`, `?`, `[...]` classes (with `^` negation and `a-z` ranges)
+/// and `\` escapes. `*` is matched by backtracking to the most recent star
+/// only, so patterns like `*?*?*` stay linear in practice instead of
+/// exploding exponentially.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+	let (mut p, mut s) = (0, 0);
+	// Pattern index just past the last `*`, and the string index it is
+	// currently assumed to have consumed up to.
+	let mut star: Option<(usize, usize)> = None;
+
+	while s < string.len() {
+		if p < pattern.len() && pattern[p] == b'*' {
+			p += 1;
+			star = Some((p, s));
+			continue;
+		}
+		if p < pattern.len() {
+			if let Some(next) = match_one(pattern, p, string[s]) {
+				p = next;
+				s += 1;
+				continue;
+			}
+		}
+		match star {
+			Some((star_p, star_s)) => {
+				p = star_p;
+				s = star_s + 1;
+				star = Some((star_p, star_s + 1));
+			}
+			None => return false,
+		}
+	}
+
+	pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Match the single-character token at `pattern[p]` against `c`, returning
+/// the index of the following token on success.
+fn match_one(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+	match pattern[p] {
+		b'?' => Some(p + 1),
+		b'\\' if p + 1 < pattern.len() => {
+			if pattern[p + 1] == c {
+				Some(p + 2)
+			} else {
+				None
+			}
+		}
+		b'[' => match_class(pattern, p + 1, c),
+		literal => {
+			if literal == c {
+				Some(p + 1)
+			} else {
+				None
+			}
+		}
+	}
+}
+
+/// Match a `[...]` class whose body starts at `pattern[p]`. An unterminated
+/// class runs to the end of the pattern, as in Redis.
+fn match_class(pattern: &[u8], mut p: usize, c: u8) -> Option<usize> {
+	let negate = p < pattern.len() && pattern[p] == b'^';
+	if negate {
+		p += 1;
+	}
+	let mut matched = false;
+	while p < pattern.len() && pattern[p] != b']' {
+		if pattern[p] == b'\\' && p + 1 < pattern.len() {
+			matched |= pattern[p + 1] == c;
+			p += 2;
+		} else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+			let (mut lo, mut hi) = (pattern[p], pattern[p + 2]);
+			if lo > hi {
+				std::mem::swap(&mut lo, &mut hi);
+			}
+			matched |= lo <= c && c <= hi;
+			p += 3;
+		} else {
+			matched |= pattern[p] == c;
+			p += 1;
+		}
+	}
+	if matched != negate {
+		Some((p + 1).min(pattern.len()))
+	} else {
+		None
+	}
+}
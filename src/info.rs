@@ -0,0 +1,134 @@
+use super::types::*;
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::process;
+
+/// Sections included by a bare `INFO` (or `INFO default`).
+const DEFAULT_SECTIONS: &[&str] = &["server", "replication"];
+/// Every section, for `INFO all`/`INFO everything`.
+const ALL_SECTIONS: &[&str] = &["server", "replication", "commandstats"];
+
+fn server_section(state: &State, out: &mut String) {
+	let _ = write!(out, "# Server\r\n");
+	let _ = write!(out, "redis_version:{}\r\n", env!("CARGO_PKG_VERSION"));
+	let _ = write!(out, "process_id:{}\r\n", process::id());
+	let _ = write!(out, "run_id:{}\r\n", state.run_id);
+	let _ = write!(
+		out,
+		"uptime_in_seconds:{}\r\n",
+		state.started_at.elapsed().as_secs()
+	);
+}
+
+fn replication_section(state: &State, out: &mut String) {
+	let _ = write!(out, "# Replication\r\n");
+	let _ = write!(out, "role:master\r\n");
+	let _ = write!(out, "connected_slaves:0\r\n");
+	let _ = write!(
+		out,
+		"master_replid:{}\r\n",
+		state.master_replid.lock().unwrap()
+	);
+	let _ = write!(out, "master_repl_offset:0\r\n");
+}
+
+fn commandstats_section(state: &State, out: &mut String) {
+	let _ = write!(out, "# Commandstats\r\n");
+	let stats = state.command_stats.lock().unwrap();
+	let mut names: Vec<&&'static str> = stats.keys().collect();
+	names.sort();
+	for name in names {
+		let stat = stats[*name];
+		let _ = write!(
+			out,
+			"cmdstat_{}:calls={},usec={},usec_per_call={:.2}\r\n",
+			name,
+			stat.calls,
+			stat.usec,
+			stat.usec as f64 / stat.calls as f64
+		);
+	}
+}
+
+/// `INFO [section ...]`
+pub fn info(args: &[RedisValue], state: &State) -> Result<ReturnValue, ReturnError> {
+	let mut sections: Vec<&str> = Vec::new();
+	let mut requested = Vec::with_capacity(args.len());
+	for arg in args {
+		let b = Bytes::try_from(arg.clone())?;
+		requested.push(String::from_utf8_lossy(&b).to_lowercase());
+	}
+	if requested.is_empty() {
+		sections.extend(DEFAULT_SECTIONS);
+	}
+	for name in &requested {
+		match name.as_str() {
+			"default" => sections.extend(DEFAULT_SECTIONS),
+			"all" | "everything" => sections.extend(ALL_SECTIONS),
+			other => {
+				if let Some(section) = ALL_SECTIONS.iter().find(|s| **s == other) {
+					sections.push(section)
+				}
+			}
+		}
+	}
+
+	let mut out = String::new();
+	for section in ALL_SECTIONS.iter().filter(|s| sections.contains(s)) {
+		if !out.is_empty() {
+			out.push_str("\r\n");
+		}
+		match *section {
+			"server" => server_section(state, &mut out),
+			"replication" => replication_section(state, &mut out),
+			"commandstats" => commandstats_section(state, &mut out),
+			_ => {}
+		}
+	}
+	Ok(ReturnValue::StringRes(Bytes::from(out)))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Session;
+
+	fn info(session: &mut Session, section: &str) -> String {
+		String::from_utf8(session.run(&["INFO", section])).unwrap()
+	}
+
+	#[test]
+	fn commandstats_counts_calls_per_command() {
+		let mut session = Session::new();
+		for _ in 0..3 {
+			session.run(&["GET", "k"]);
+		}
+		session.run(&["SET", "k", "v"]);
+		let stats = info(&mut session, "commandstats");
+		assert!(stats.contains("# Commandstats\r\n"));
+		assert!(stats.contains("cmdstat_get:calls=3,usec="), "{}", stats);
+		assert!(stats.contains("cmdstat_set:calls=1,usec="), "{}", stats);
+	}
+
+	fn field(info: &str, name: &str) -> String {
+		let prefix = format!("{}:", name);
+		let line = info.split("\r\n").find(|line| line.starts_with(&prefix));
+		line.unwrap_or_else(|| panic!("no {} in {}", name, info))[prefix.len()..].to_string()
+	}
+
+	#[test]
+	fn change_repl_id_keeps_the_run_id() {
+		let mut session = Session::new();
+		let before = info(&mut session, "everything");
+		let run_id = field(&before, "run_id");
+		let replid = field(&before, "master_replid");
+		assert_eq!(run_id.len(), 40);
+		assert_eq!(replid.len(), 40);
+		assert!(run_id.bytes().all(|b| b.is_ascii_hexdigit()));
+
+		assert_eq!(session.run(&["DEBUG", "CHANGE-REPL-ID"]), b"+OK\r\n");
+		let after = info(&mut session, "everything");
+		assert_eq!(field(&after, "run_id"), run_id);
+		assert_ne!(field(&after, "master_replid"), replid);
+	}
+}
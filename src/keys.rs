@@ -0,0 +1,221 @@
+use super::db::now_ms;
+use super::types::*;
+use bytes::Bytes;
+use std::convert::TryFrom;
+
+/// Call `f` with each key in `args` and count how many calls return true.
+///
+/// Shared by the variadic keyspace commands so they agree on how keys are
+/// read and counted. Repeated keys are visited (and counted) each time,
+/// as in Redis.
+pub fn for_each_key(args: &[RedisValue], mut f: impl FnMut(&[u8]) -> bool) -> i64 {
+	let mut count = 0;
+	for arg in args {
+		if let RedisValue::String(key) = arg {
+			if f(key) {
+				count += 1;
+			}
+		}
+	}
+	count
+}
+
+/// `DEL key [key ...]`, also used for `UNLINK`.
+pub fn del(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let mut db = state.db.lock().unwrap();
+	let removed = for_each_key(&a[1..], |key| db.remove(key).is_some());
+	Ok(ReturnValue::IntRes(removed))
+}
+
+/// `EXISTS key [key ...]`, also used for `TOUCH`, which has no access time
+/// to update here.
+pub fn exists(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let mut db = state.db.lock().unwrap();
+	let found = for_each_key(&a[1..], |key| db.contains_key(key));
+	Ok(ReturnValue::IntRes(found))
+}
+
+/// The expiry deadline of `a[1]` in Unix milliseconds, or Redis' -2 (no
+/// such key) / -1 (no expiry) sentinels.
+fn deadline_ms(a: &[RedisValue], state: &State) -> Result<i64, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let mut db = state.db.lock().unwrap();
+	if !db.contains_key(&key) {
+		return Ok(-2);
+	}
+	Ok(match db.expiry(&key) {
+		Some(deadline) => deadline as i64,
+		None => -1,
+	})
+}
+
+/// Remaining time to live of `a[1]` in milliseconds, with the same
+/// sentinels as `deadline_ms`.
+fn remaining_ms(a: &[RedisValue], state: &State) -> Result<i64, ReturnError> {
+	Ok(match deadline_ms(a, state)? {
+		sentinel if sentinel < 0 => sentinel,
+		deadline => (deadline as u64).saturating_sub(now_ms()) as i64,
+	})
+}
+
+/// Give `key` the absolute deadline `deadline` (Unix milliseconds). A
+/// deadline already in the past deletes the key instead, as in Redis.
+/// Replies 1 if the key existed, 0 otherwise.
+fn expire_key_at(key: &[u8], deadline: i64, state: &State) -> ReturnValue {
+	let mut db = state.db.lock().unwrap();
+	let existed = if deadline <= now_ms() as i64 {
+		db.remove(key).is_some()
+	} else {
+		db.set_expiry(key, deadline as u64)
+	};
+	ReturnValue::IntRes(existed as i64)
+}
+
+/// `EXPIRE key seconds`
+pub fn expire(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	expire_command(a, state, "expire", 1000, true)
+}
+
+/// `PEXPIRE key milliseconds`
+pub fn pexpire(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	expire_command(a, state, "pexpire", 1, true)
+}
+
+/// `EXPIREAT key unix-time-seconds`
+pub fn expireat(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	expire_command(a, state, "expireat", 1000, false)
+}
+
+/// `PEXPIREAT key unix-time-milliseconds`
+pub fn pexpireat(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	expire_command(a, state, "pexpireat", 1, false)
+}
+
+/// Shared by the `EXPIRE` family, whose argument is in units of `scale`
+/// milliseconds and, if `relative`, counts from now. A zero or negative
+/// TTL deletes the key, like a timestamp in the past.
+fn expire_command(
+	a: &[RedisValue],
+	state: &State,
+	name: &str,
+	scale: i64,
+	relative: bool,
+) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let base = if relative { now_ms() as i64 } else { 0 };
+	let deadline = match integer_arg(&a[2]) {
+		Some(n) => match n.checked_mul(scale).and_then(|ms| ms.checked_add(base)) {
+			Some(deadline) => deadline,
+			None => return Ok(invalid_expire_time(name)),
+		},
+		None => return Ok(not_an_integer()),
+	};
+	Ok(expire_key_at(&key, deadline, state))
+}
+
+/// `EXPIRETIME key`
+pub fn expiretime(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	Ok(ReturnValue::IntRes(match deadline_ms(a, state)? {
+		sentinel if sentinel < 0 => sentinel,
+		deadline => deadline / 1000,
+	}))
+}
+
+/// `PEXPIRETIME key`
+pub fn pexpiretime(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	Ok(ReturnValue::IntRes(deadline_ms(a, state)?))
+}
+
+/// `TTL key`
+pub fn ttl(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let ms = remaining_ms(a, state)?;
+	// Rounded to the nearest second, as Redis does.
+	Ok(ReturnValue::IntRes(if ms < 0 { ms } else { (ms + 500) / 1000 }))
+}
+
+/// `PTTL key`
+pub fn pttl(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	Ok(ReturnValue::IntRes(remaining_ms(a, state)?))
+}
+
+/// `TYPE key`
+pub fn key_type(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let name = match state.db.lock().unwrap().get_value(&key) {
+		Some(value) => value.type_name(),
+		None => "none",
+	};
+	Ok(ReturnValue::SimpleString(Bytes::from(name)))
+}
+
+/// `DBSIZE`
+pub fn dbsize(_a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	Ok(ReturnValue::IntRes(state.db.lock().unwrap().len() as i64))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Session;
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn dbsize_counts_expired_keys_until_they_are_looked_up() {
+		let mut session = Session::new();
+		session.run(&["MSET", "a", "1", "b", "2", "c", "3"]);
+		assert_eq!(session.run(&["PEXPIRE", "a", "1"]), b":1\r\n");
+		assert_eq!(session.run(&["PEXPIRE", "b", "100000"]), b":1\r\n");
+		thread::sleep(Duration::from_millis(5));
+		assert_eq!(session.run(&["DBSIZE"]), b":3\r\n");
+		assert_eq!(session.run(&["EXISTS", "a"]), b":0\r\n");
+		assert_eq!(session.run(&["DBSIZE"]), b":2\r\n");
+	}
+
+	#[test]
+	fn expiretime_reports_the_expireat_deadline() {
+		let mut session = Session::new();
+		session.run(&["SET", "k", "v"]);
+		assert_eq!(session.run(&["EXPIRETIME", "k"]), b":-1\r\n");
+		assert_eq!(session.run(&["EXPIRETIME", "missing"]), b":-2\r\n");
+		assert_eq!(session.run(&["EXPIREAT", "k", "33177117420"]), b":1\r\n");
+		assert_eq!(session.run(&["EXPIRETIME", "k"]), b":33177117420\r\n");
+		assert_eq!(session.run(&["PEXPIRETIME", "k"]), b":33177117420000\r\n");
+		assert_eq!(session.run(&["PEXPIREAT", "k", "33177117420123"]), b":1\r\n");
+		assert_eq!(session.run(&["PEXPIRETIME", "k"]), b":33177117420123\r\n");
+		assert_eq!(session.run(&["EXPIRETIME", "k"]), b":33177117420\r\n");
+		assert_eq!(session.run(&["EXPIREAT", "missing", "33177117420"]), b":0\r\n");
+	}
+
+	#[test]
+	fn non_positive_expire_deletes_the_key() {
+		let mut session = Session::new();
+		session.run(&["MSET", "a", "1", "b", "2"]);
+		assert_eq!(session.run(&["EXPIRE", "a", "-1"]), b":1\r\n");
+		assert_eq!(session.run(&["EXISTS", "a"]), b":0\r\n");
+		assert_eq!(session.run(&["PEXPIRE", "b", "0"]), b":1\r\n");
+		assert_eq!(session.run(&["GET", "b"]), b"$-1\r\n");
+		assert_eq!(session.run(&["EXPIRE", "missing", "-1"]), b":0\r\n");
+		assert_eq!(session.run(&["DBSIZE"]), b":0\r\n");
+	}
+
+	#[test]
+	fn exists_counts_repeated_keys_each_time() {
+		let mut session = Session::new();
+		session.run(&["MSET", "a", "1", "b", "2"]);
+		assert_eq!(session.run(&["EXISTS", "a", "a", "missing", "b"]), b":3\r\n");
+		assert_eq!(session.run(&["TOUCH", "a", "missing"]), b":1\r\n");
+		assert_eq!(session.run(&["EXISTS", "missing", "missing"]), b":0\r\n");
+	}
+
+	#[test]
+	fn del_counts_each_key_removed_once() {
+		let mut session = Session::new();
+		session.run(&["MSET", "a", "1", "b", "2", "c", "3"]);
+		// A repeated key is only there to remove the first time.
+		assert_eq!(session.run(&["DEL", "a", "a", "missing", "b"]), b":2\r\n");
+		assert_eq!(session.run(&["EXISTS", "a", "b", "c"]), b":1\r\n");
+		assert_eq!(session.run(&["UNLINK", "c", "c"]), b":1\r\n");
+		assert_eq!(session.run(&["DEL", "missing"]), b":0\r\n");
+		assert_eq!(session.run(&["DBSIZE"]), b":0\r\n");
+	}
+}
@@ -1,5 +1,45 @@
+#[macro_use]
+pub mod logging;
+pub mod acl;
+pub mod client;
+pub mod commands;
+pub mod db;
+pub mod debug;
+pub mod glob;
+pub mod info;
+pub mod keys;
+pub mod memory;
+pub mod object;
+pub mod pool;
+pub mod random;
 pub mod redis;
+pub mod replication;
+pub mod resp;
+pub mod server;
+pub mod slowlog;
+pub mod stream;
+pub mod strings;
+#[cfg(test)]
+mod testing;
 pub mod types;
 
+pub use acl::*;
+pub use client::*;
+pub use commands::*;
+pub use db::*;
+pub use debug::*;
+pub use glob::*;
+pub use info::*;
+pub use keys::*;
+pub use memory::*;
+pub use object::*;
+pub use pool::*;
+pub use random::*;
 pub use redis::*;
+pub use replication::*;
+pub use resp::*;
+pub use server::*;
+pub use slowlog::*;
+pub use stream::*;
+pub use strings::*;
 pub use types::*;
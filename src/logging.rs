@@ -0,0 +1,130 @@
+#[cfg(test)]
+use std::cell::RefCell;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Log levels, from least to most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+	Error = 0,
+	Warn = 1,
+	Info = 2,
+	Debug = 3,
+}
+
+impl fmt::Display for Level {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Level::Error => write!(f, "error"),
+			Level::Warn => write!(f, "warn"),
+			Level::Info => write!(f, "info"),
+			Level::Debug => write!(f, "debug"),
+		}
+	}
+}
+
+impl FromStr for Level {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Level, ()> {
+		match s.to_lowercase().as_str() {
+			"error" => Ok(Level::Error),
+			"warn" | "warning" => Ok(Level::Warn),
+			"info" => Ok(Level::Info),
+			"debug" => Ok(Level::Debug),
+			_ => Err(()),
+		}
+	}
+}
+
+/// Environment variable read for the initial level.
+pub const LOG_LEVEL_ENV: &str = "REDIS_LOG_LEVEL";
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+pub fn set_level(level: Level) {
+	LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+	level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Write a message if `level` is enabled. Errors and warnings go to stderr,
+/// everything else to stdout.
+pub fn log(level: Level, args: fmt::Arguments) {
+	if !enabled(level) {
+		return;
+	}
+	#[cfg(test)]
+	{
+		let captured = CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+			Some(lines) => {
+				lines.push(format!("[{}] {}", level, args));
+				true
+			}
+			None => false,
+		});
+		if captured {
+			return;
+		}
+	}
+	match level {
+		Level::Error | Level::Warn => eprintln!("[{}] {}", level, args),
+		Level::Info | Level::Debug => println!("[{}] {}", level, args),
+	}
+}
+
+#[cfg(test)]
+thread_local! {
+	static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, returning what it logged on this thread instead of printing it.
+#[cfg(test)]
+pub fn capture(f: impl FnOnce()) -> Vec<String> {
+	CAPTURED.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+	f();
+	CAPTURED.with(|captured| captured.borrow_mut().take().unwrap_or_default())
+}
+
+#[macro_export]
+macro_rules! log_error {
+	($($arg:tt)*) => { $crate::logging::log($crate::logging::Level::Error, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+	($($arg:tt)*) => { $crate::logging::log($crate::logging::Level::Warn, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+	($($arg:tt)*) => { $crate::logging::log($crate::logging::Level::Info, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+	($($arg:tt)*) => { $crate::logging::log($crate::logging::Level::Debug, format_args!($($arg)*)) };
+}
+
+#[cfg(test)]
+mod tests {
+	use super::capture;
+	use crate::testing::Session;
+
+	#[test]
+	fn info_level_logs_no_command_payloads() {
+		let mut session = Session::new();
+		let lines = capture(|| {
+			session.run(&["SET", "secret-key", "secret-value"]);
+			// A malformed frame is logged, but not its contents.
+			session.send(b"*1\r\n$x-secret-frame\r\n");
+		});
+		assert!(!lines.is_empty());
+		for line in &lines {
+			assert!(!line.contains("secret"), "{}", line);
+		}
+	}
+}
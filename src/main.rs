@@ -1,86 +1,202 @@
+#[macro_use]
+mod logging;
+mod acl;
+mod client;
+mod commands;
+mod db;
+mod debug;
+mod glob;
+mod info;
+mod keys;
+mod memory;
+mod object;
+mod pool;
+mod random;
 mod redis;
+mod replication;
+mod resp;
+mod server;
+mod slowlog;
+mod stream;
+mod strings;
+#[cfg(test)]
+mod testing;
 mod types;
 
-use bytes::{Bytes, BytesMut};
-use std::collections::HashMap;
-
-use redis::*;
-#[allow(unused_imports)]
+use pool::ThreadPool;
+use server::*;
 use std::env;
 use types::*;
 
 #[allow(unused_imports)]
 use std::fs;
-#[allow(unused_imports)]
-use std::io::{Error, Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, Mutex};
+use std::net::{Shutdown, TcpListener};
+use std::os::unix::net::UnixListener;
+use std::sync::Arc;
 use std::thread;
 
-fn handle_command(stream: &mut TcpStream, redis_value: RedisValue, state: &mut State) {
-    match ReturnValue::parse_redis_value(redis_value, state) {
-        Ok(ReturnValue::StringRes(s)) => {
-            // let test = String::from_utf8_lossy(&s);
-            // println!("return value {}", test);
-            let _ = stream.write(&s);
-        }
-        Ok(ReturnValue::Ok) => {
-            let ok_response = write_simple_string(Bytes::from("OK"));
-            let _ = stream.write(&ok_response);
-        }
-        Ok(ReturnValue::Nil) => {
-            let nil_response = write_bulk_string_nil();
-            let _ = stream.write(&nil_response);
-        }
-        _ => {
-            println!("Cannot find return value");
-        }
-    }
+/// Server options taken from the command line.
+struct Config {
+    /// Path of a Unix domain socket to accept connections on, in addition
+    /// to the TCP port.
+    unixsocket: Option<String>,
+    loglevel: Option<logging::Level>,
+    /// Number of worker threads, and so the maximum number of connections
+    /// served concurrently. Connections beyond that are refused.
+    threads: usize,
+    proto_max_bulk_len: usize,
+    max_nesting_depth: usize,
+    read_only: bool,
+    slowlog_log_slower_than: i64,
+    slowlog_max_len: usize,
+    expire_jitter: u64,
 }
 
-fn handle_message(stream: &mut TcpStream, buf: &mut BytesMut, state: &mut State) {
-    println!("Received data: {}.", String::from_utf8_lossy(&buf));
-    match parse(buf, 0) {
-        Ok(result) => match result {
-            Some((pos, value)) => {
-                let data = buf.split_to(pos);
-                let redis_value = value.redis_value(&data.freeze());
-                handle_command(stream, redis_value, state)
+const DEFAULT_THREADS: usize = 64;
+
+impl Config {
+    fn from_args() -> Config {
+        let mut config = Config {
+            unixsocket: None,
+            loglevel: None,
+            threads: DEFAULT_THREADS,
+            proto_max_bulk_len: DEFAULT_PROTO_MAX_BULK_LEN,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            read_only: false,
+            slowlog_log_slower_than: slowlog::DEFAULT_SLOWLOG_LOG_SLOWER_THAN,
+            slowlog_max_len: slowlog::DEFAULT_SLOWLOG_MAX_LEN,
+            expire_jitter: 0,
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--unixsocket" => config.unixsocket = args.next(),
+                "--loglevel" => match args.next().map(|level| level.parse()) {
+                    Some(Ok(level)) => config.loglevel = Some(level),
+                    _ => log_warn!("Ignoring invalid --loglevel"),
+                },
+                "--threads" => match args.next().map(|threads| threads.parse()) {
+                    Some(Ok(threads)) if threads > 0 => config.threads = threads,
+                    _ => log_warn!("Ignoring invalid --threads"),
+                },
+                "--proto-max-bulk-len" => match args.next().map(|len| len.parse()) {
+                    Some(Ok(len)) => config.proto_max_bulk_len = len,
+                    _ => log_warn!("Ignoring invalid --proto-max-bulk-len"),
+                },
+                "--max-nesting-depth" => match args.next().map(|depth| depth.parse()) {
+                    Some(Ok(depth)) if depth > 0 => config.max_nesting_depth = depth,
+                    _ => log_warn!("Ignoring invalid --max-nesting-depth"),
+                },
+                "--read-only" => config.read_only = true,
+                "--slowlog-log-slower-than" => match args.next().map(|usec| usec.parse()) {
+                    Some(Ok(usec)) => config.slowlog_log_slower_than = usec,
+                    _ => log_warn!("Ignoring invalid --slowlog-log-slower-than"),
+                },
+                "--slowlog-max-len" => match args.next().map(|len| len.parse()) {
+                    Some(Ok(len)) => config.slowlog_max_len = len,
+                    _ => log_warn!("Ignoring invalid --slowlog-max-len"),
+                },
+                "--expire-jitter" => match args.next().map(|percent| percent.parse()) {
+                    Some(Ok(percent)) if percent <= 100 => config.expire_jitter = percent,
+                    _ => log_warn!("Ignoring invalid --expire-jitter"),
+                },
+                _ => log_warn!("Ignoring unknown argument {}", arg),
             }
-            None => {}
-        },
-        Err(e) => {
-            println!("Error parsing: {}", e)
         }
+        config
     }
 }
 
-fn handle_client(mut stream: TcpStream, state: &mut State) -> Result<(), Error> {
-    println!("Incoming connection from: {}", stream.peer_addr()?);
-    let mut temp_buf = [0; MESSAGE_SIZE];
-
-    loop {
-        let bytes_read = stream.read(&mut temp_buf)?;
-        let mut buf = BytesMut::from(&temp_buf[..]);
-        handle_message(&mut stream, &mut buf, state);
-        if bytes_read == 0 {
-            return Ok(());
+fn listen_unix(path: String, state: State, pool: Arc<ThreadPool>) {
+    // Like Redis, take over a socket file left behind by a previous run.
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    for stream in listener.incoming() {
+        match stream {
+            Err(e) => log_error!("failed {}", e),
+            Ok(stream) => {
+                let mut state = Arc::clone(&state);
+                let peer = path.clone();
+                let shutdown = match stream.try_clone() {
+                    Ok(handle) => Box::new(move || {
+                        let _ = handle.shutdown(Shutdown::Both);
+                    }) as Box<dyn Fn() + Send>,
+                    Err(e) => {
+                        log_error!("failed {}", e);
+                        continue;
+                    }
+                };
+                let served = pool.try_execute(stream, move |stream| {
+                    handle_client(stream, peer, shutdown, &mut state)
+                        .unwrap_or_else(|error| log_error!("failed {:?}", error));
+                });
+                if let Err(stream) = served {
+                    refuse_client(stream);
+                }
+            }
         }
     }
 }
 
 fn main() {
+    if let Ok(Ok(level)) = env::var(logging::LOG_LEVEL_ENV).map(|level| level.parse()) {
+        logging::set_level(level);
+    }
+    let config = Config::from_args();
+    if let Some(level) = config.loglevel {
+        logging::set_level(level);
+    }
     let listener = TcpListener::bind("127.0.0.1:6379").unwrap();
-    let state: State = Arc::new(Mutex::new(HashMap::new()));
+    let mut server = ServerState::new();
+    server.proto_max_bulk_len = config.proto_max_bulk_len;
+    server.max_nesting_depth = config.max_nesting_depth;
+    server.read_only = config.read_only;
+    server.slowlog_log_slower_than = config.slowlog_log_slower_than;
+    server.slowlog_max_len = config.slowlog_max_len;
+    server.expire_jitter = config.expire_jitter;
+    let state: State = Arc::new(server);
+    let pool = Arc::new(ThreadPool::new(config.threads));
+    log_info!("Serving up to {} connections at once", pool.size());
+
+    {
+        let state = Arc::clone(&state);
+        thread::spawn(move || loop {
+            thread::sleep(ACTIVE_EXPIRE_INTERVAL);
+            state.active_expire_cycle();
+        });
+    }
+
+    if let Some(path) = config.unixsocket {
+        let state = Arc::clone(&state);
+        let pool = Arc::clone(&pool);
+        thread::spawn(move || listen_unix(path, state, pool));
+    }
+
     for stream in listener.incoming() {
         match stream {
-            Err(e) => eprintln!("failed {}", e),
+            Err(e) => log_error!("failed {}", e),
             Ok(stream) => {
                 let mut state = Arc::clone(&state);
-                thread::spawn(move || {
-                    handle_client(stream, &mut state)
-                        .unwrap_or_else(|error| eprintln!("failed {:?}", error));
+                let shutdown = match stream.try_clone() {
+                    Ok(handle) => Box::new(move || {
+                        let _ = handle.shutdown(Shutdown::Both);
+                    }) as Box<dyn Fn() + Send>,
+                    Err(e) => {
+                        log_error!("failed {}", e);
+                        continue;
+                    }
+                };
+                let served = pool.try_execute(stream, move |stream| {
+                    let peer = stream
+                        .peer_addr()
+                        .map(|addr| addr.to_string())
+                        .unwrap_or_default();
+                    handle_client(stream, peer, shutdown, &mut state)
+                        .unwrap_or_else(|error| log_error!("failed {:?}", error));
                 });
+                if let Err(stream) = served {
+                    refuse_client(stream);
+                }
             }
         }
     }
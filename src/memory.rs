@@ -0,0 +1,110 @@
+use super::db::Value;
+use super::stream::StreamId;
+use super::types::*;
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::mem::size_of;
+
+const MEMORY_HELP: &[&str] = &[
+	"DOCTOR",
+	"    Return memory problems reports.",
+	"USAGE <key> [SAMPLES <count>]",
+	"    Return memory in bytes used by <key> and its value.",
+];
+
+/// Rough cost of one keyspace entry beyond its bytes: the hash table slot
+/// and a `Bytes` handle each for the key and the value.
+const ENTRY_OVERHEAD: usize = size_of::<u64>() + 2 * size_of::<Bytes>();
+
+/// Rough cost of one stream entry beyond its fields and values: the id
+/// and the pair list.
+const STREAM_ENTRY_OVERHEAD: usize = size_of::<StreamId>() + size_of::<Vec<(Bytes, Bytes)>>();
+
+fn value_size(value: &Value) -> usize {
+	match value {
+		Value::String(value) => value.len(),
+		Value::Int(_) => size_of::<i64>(),
+		Value::Stream(stream) => stream
+			.iter()
+			.map(|(_, fields)| {
+				let pairs: usize = fields
+					.iter()
+					.map(|(field, value)| field.len() + value.len())
+					.sum();
+				pairs + STREAM_ENTRY_OVERHEAD
+			})
+			.sum(),
+	}
+}
+
+/// `MEMORY <subcommand> [arg ...]`
+pub fn memory(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let subcommand = Bytes::try_from(a[1].clone())?;
+	match subcommand.to_ascii_lowercase().as_slice() {
+		b"help" => Ok(subcommand_help("MEMORY", MEMORY_HELP)),
+		b"doctor" => Ok(ReturnValue::StringRes(Bytes::from(
+			"Hi Sam, I can't find any memory issue in your instance. \
+			 I can only account for what occurs on this base.",
+		))),
+		b"usage" => usage(a, state),
+		_ => Ok(unknown_subcommand("MEMORY", &subcommand)),
+	}
+}
+
+/// `MEMORY USAGE key [SAMPLES count]`. Every entry is counted, so
+/// `SAMPLES` is accepted but has nothing to sample.
+fn usage(a: &[RedisValue], state: &State) -> Result<ReturnValue, ReturnError> {
+	match a.len() {
+		// MEMORY's own arity allows a bare USAGE, with no key.
+		2 => return Ok(wrong_number_of_arguments("memory|usage")),
+		3 => {}
+		5 => {
+			let option = Bytes::try_from(a[3].clone())?;
+			if option.to_ascii_lowercase() != b"samples" {
+				return Ok(syntax_error());
+			}
+			if integer_arg(&a[4]).is_none() {
+				return Ok(not_an_integer());
+			}
+		}
+		_ => return Ok(syntax_error()),
+	}
+	let key = Bytes::try_from(a[2].clone())?;
+	let mut db = state.db.lock().unwrap();
+	Ok(match db.get_value(&key) {
+		Some(value) => ReturnValue::IntRes((key.len() + value_size(value) + ENTRY_OVERHEAD) as i64),
+		None => ReturnValue::Nil,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Session;
+
+	fn usage(session: &mut Session, key: &str) -> i64 {
+		let reply = session.run(&["MEMORY", "USAGE", key]);
+		assert_eq!(reply[0], b':', "{:?}", String::from_utf8_lossy(&reply));
+		String::from_utf8_lossy(&reply[1..reply.len() - 2]).parse().unwrap()
+	}
+
+	#[test]
+	fn usage_covers_at_least_the_key_and_value() {
+		let mut session = Session::new();
+		let value = "x".repeat(1000);
+		session.run(&["SET", "big", &value]);
+		session.run(&["SET", "small", "x"]);
+		assert!(usage(&mut session, "big") >= 1003);
+		assert!(usage(&mut session, "small") >= 6);
+		assert!(usage(&mut session, "big") > usage(&mut session, "small"));
+	}
+
+	#[test]
+	fn usage_of_a_missing_key_is_nil() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["MEMORY", "USAGE", "missing"]), b"$-1\r\n");
+		assert_eq!(
+			session.run(&["MEMORY", "USAGE", "missing", "SAMPLES", "five"]),
+			&b"-ERR value is not an integer or out of range\r\n"[..]
+		);
+	}
+}
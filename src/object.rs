@@ -0,0 +1,114 @@
+use super::db::Value;
+use super::types::*;
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::str;
+
+const OBJECT_HELP: &[&str] = &[
+	"ENCODING <key>",
+	"    Return the kind of internal representation used in order to store the value",
+	"    associated with a <key>.",
+	"IDLETIME <key>",
+	"    Return the idle time of the key, that is the approximated number of",
+	"    seconds elapsed since the last access to the key.",
+];
+
+/// Longest string Redis stores in a single allocation with its header.
+const EMBSTR_SIZE_LIMIT: usize = 44;
+
+/// The `i64` whose canonical decimal form `value` is, if any. Strings
+/// such as `012` or `+1` parse as numbers but aren't canonical.
+pub fn canonical_int(value: &[u8]) -> Option<i64> {
+	let n = str::from_utf8(value).ok()?.parse::<i64>().ok()?;
+	if n.to_string().as_bytes() == value {
+		Some(n)
+	} else {
+		None
+	}
+}
+
+/// The encoding Redis would report for a string value: `int` when it is
+/// the canonical decimal form of an `i64`, otherwise `embstr` or `raw` by
+/// length.
+pub fn string_encoding(value: &[u8]) -> &'static str {
+	if canonical_int(value).is_some() {
+		"int"
+	} else if value.len() <= EMBSTR_SIZE_LIMIT {
+		"embstr"
+	} else {
+		"raw"
+	}
+}
+
+/// `OBJECT <subcommand> [arg ...]`
+pub fn object(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let subcommand = Bytes::try_from(a[1].clone())?;
+	match subcommand.to_ascii_lowercase().as_slice() {
+		b"help" => Ok(subcommand_help("OBJECT", OBJECT_HELP)),
+		b"encoding" => {
+			if a.len() != 3 {
+				return Ok(wrong_number_of_arguments("object|encoding"));
+			}
+			let key = Bytes::try_from(a[2].clone())?;
+			Ok(match state.db.lock().unwrap().get_value(&key) {
+				Some(Value::String(value)) => ReturnValue::StringRes(Bytes::from(string_encoding(value))),
+				Some(Value::Int(_)) => ReturnValue::StringRes(Bytes::from("int")),
+				Some(Value::Stream(_)) => ReturnValue::StringRes(Bytes::from("stream")),
+				None => ReturnValue::Nil,
+			})
+		}
+		// OBJECT names no keys in the command table, so looking at a key
+		// doesn't update its access time.
+		b"idletime" => {
+			if a.len() != 3 {
+				return Ok(wrong_number_of_arguments("object|idletime"));
+			}
+			let key = Bytes::try_from(a[2].clone())?;
+			Ok(match state.db.lock().unwrap().idle_time(&key) {
+				Some(ms) => ReturnValue::IntRes((ms / 1000) as i64),
+				None => ReturnValue::Nil,
+			})
+		}
+		_ => Ok(unknown_subcommand("OBJECT", &subcommand)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Session;
+
+	fn encoding(session: &mut Session, key: &str) -> Vec<u8> {
+		session.run(&["OBJECT", "ENCODING", key])
+	}
+
+	#[test]
+	fn encoding_tracks_the_string_contents() {
+		let mut session = Session::new();
+		session.run(&["MSET", "n", "12345", "padded", "012", "s", "hello"]);
+		session.run(&["SET", "long", &"x".repeat(45)]);
+		assert_eq!(encoding(&mut session, "n"), b"$3\r\nint\r\n");
+		assert_eq!(encoding(&mut session, "padded"), b"$6\r\nembstr\r\n");
+		assert_eq!(encoding(&mut session, "s"), b"$6\r\nembstr\r\n");
+		assert_eq!(encoding(&mut session, "long"), b"$3\r\nraw\r\n");
+		assert_eq!(encoding(&mut session, "missing"), b"$-1\r\n");
+	}
+
+	#[test]
+	fn incr_keeps_the_int_encoding() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["INCR", "counter"]), b":1\r\n");
+		assert_eq!(encoding(&mut session, "counter"), b"$3\r\nint\r\n");
+		session.run(&["INCRBY", "counter", "41"]);
+		assert_eq!(encoding(&mut session, "counter"), b"$3\r\nint\r\n");
+		assert_eq!(session.run(&["APPEND", "counter", "x"]), b":3\r\n");
+		assert_eq!(encoding(&mut session, "counter"), b"$6\r\nembstr\r\n");
+	}
+
+	#[test]
+	fn idletime_is_nil_for_missing_keys() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["OBJECT", "IDLETIME", "missing"]), b"$-1\r\n");
+		session.run(&["SET", "k", "v"]);
+		assert_eq!(session.run(&["OBJECT", "IDLETIME", "k"]), b":0\r\n");
+	}
+}
@@ -0,0 +1,149 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed set of worker threads fed from a shared queue.
+///
+/// Each connection occupies a worker until it closes, so the pool size caps
+/// the number of connections served at once. `try_execute` refuses work
+/// once every worker is taken, rather than leaving it queued behind
+/// connections that may never close.
+pub struct ThreadPool {
+	sender: Sender<Job>,
+	size: usize,
+	/// Jobs queued or running.
+	busy: Arc<AtomicUsize>,
+}
+
+impl ThreadPool {
+	pub fn new(size: usize) -> ThreadPool {
+		assert!(size > 0, "thread pool needs at least one worker");
+		let (sender, receiver) = channel::<Job>();
+		let receiver = Arc::new(Mutex::new(receiver));
+		let busy = Arc::new(AtomicUsize::new(0));
+		for id in 0..size {
+			let receiver = Arc::clone(&receiver);
+			let busy = Arc::clone(&busy);
+			thread::Builder::new()
+				.name(format!("worker-{}", id))
+				.spawn(move || worker(receiver, busy))
+				.expect("failed to spawn worker thread");
+		}
+		ThreadPool { sender, size, busy }
+	}
+
+	pub fn size(&self) -> usize {
+		self.size
+	}
+
+	/// Queue `job` to run on the next free worker, however many are busy.
+	#[allow(dead_code)]
+	pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+		self.busy.fetch_add(1, Ordering::SeqCst);
+		// Workers only exit once every sender is gone, so this can't fail
+		// while the pool is alive.
+		let _ = self.sender.send(Box::new(job));
+	}
+
+	/// Run `job(value)` if a worker is free, or hand `value` back if every
+	/// worker is already taken.
+	pub fn try_execute<T, F>(&self, value: T, job: F) -> Result<(), T>
+	where
+		T: Send + 'static,
+		F: FnOnce(T) + Send + 'static,
+	{
+		let size = self.size;
+		let reserved = self
+			.busy
+			.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |busy| {
+				if busy < size {
+					Some(busy + 1)
+				} else {
+					None
+				}
+			});
+		if reserved.is_err() {
+			return Err(value);
+		}
+		let _ = self.sender.send(Box::new(move || job(value)));
+		Ok(())
+	}
+}
+
+fn worker(receiver: Arc<Mutex<Receiver<Job>>>, busy: Arc<AtomicUsize>) {
+	loop {
+		let job = match receiver.lock().unwrap().recv() {
+			Ok(job) => job,
+			Err(_) => return,
+		};
+		// A panicking job must not take its worker with it, or the pool
+		// shrinks until nothing is left to serve connections.
+		if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+			log_error!("Worker job panicked");
+		}
+		busy.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::ThreadPool;
+	use std::collections::HashSet;
+	use std::sync::mpsc::channel;
+	use std::thread;
+
+	#[test]
+	fn jobs_run_on_at_most_size_threads() {
+		let pool = ThreadPool::new(2);
+		let (sender, receiver) = channel();
+		for _ in 0..20 {
+			let sender = sender.clone();
+			pool.execute(move || {
+				sender.send(thread::current().name().unwrap().to_string()).unwrap();
+			});
+		}
+		let names: HashSet<String> = receiver.iter().take(20).collect();
+		assert!(!names.is_empty() && names.len() <= 2, "{:?}", names);
+	}
+
+	#[test]
+	fn panicking_jobs_do_not_shrink_the_pool() {
+		let pool = ThreadPool::new(2);
+		for _ in 0..4 {
+			pool.execute(|| panic!("job failed"));
+		}
+		let (sender, receiver) = channel();
+		for _ in 0..2 {
+			let sender = sender.clone();
+			pool.execute(move || sender.send(()).unwrap());
+		}
+		assert_eq!(receiver.iter().take(2).count(), 2);
+	}
+
+	#[test]
+	fn try_execute_refuses_once_every_worker_is_taken() {
+		let pool = ThreadPool::new(1);
+		let (release, held) = channel::<()>();
+		let (started, running) = channel();
+		assert!(pool
+			.try_execute(held, move |held| {
+				started.send(()).unwrap();
+				let _ = held.recv();
+			})
+			.is_ok());
+		running.recv().unwrap();
+		assert_eq!(pool.try_execute(7, |_| {}), Err(7));
+
+		release.send(()).unwrap();
+		let (done, finished) = channel();
+		// The worker frees up only once the held job has returned.
+		while pool.try_execute(done.clone(), |done| done.send(()).unwrap()).is_err() {
+			thread::yield_now();
+		}
+		finished.recv().unwrap();
+	}
+}
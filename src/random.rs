@@ -0,0 +1,18 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A random `u64`, good enough for ids and TTL jitter but not for anything
+/// that must be unpredictable.
+pub fn random_u64() -> u64 {
+	// RandomState is seeded from the OS on creation, and mixing in the time
+	// keeps two calls within one seed apart.
+	let mut hasher = RandomState::new().build_hasher();
+	hasher.write_u128(
+		SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_nanos())
+			.unwrap_or(0),
+	);
+	hasher.finish()
+}
@@ -12,6 +12,9 @@ pub fn word(buf: &BytesMut, pos: usize) -> Result<Option<(usize, BufSplit)>, RES
 	// Find the position of '\r' in the buffer
 	let mut end = pos;
 	while buf[end] != b'\r' {
+		if end - pos >= PROTO_INLINE_MAX_SIZE {
+			return Err(RESPError::TooBigLine);
+		}
 		if buf.len() <= end + 1 {
 			return Ok(None);
 		} else {
@@ -20,7 +23,7 @@ pub fn word(buf: &BytesMut, pos: usize) -> Result<Option<(usize, BufSplit)>, RES
 	}
 
 	if end + 1 < buf.len() {
-		return Ok(Some((end + 2, BufSplit(pos, end))));
+		Ok(Some((end + 2, BufSplit(pos, end))))
 	} else {
 		Ok(None)
 	}
@@ -37,19 +40,21 @@ pub fn integer(buf: &BytesMut, pos: usize) -> Result<Option<(usize, i64)>, RESPE
 	}
 }
 
-pub fn bulk_string(buf: &BytesMut, pos: usize) -> RedisResult {
+/// Bulk strings longer than `max_len` (`proto-max-bulk-len`) are rejected
+/// from the header, before any of the payload is buffered.
+pub fn bulk_string(buf: &BytesMut, pos: usize, max_len: usize) -> RedisResult {
 	match integer(buf, pos)? {
 		Some((_pos, -1)) => Err(RESPError::NullBulkString),
 		Some((pos, bulk_size)) => {
-			if bulk_size < 0 {
-				return Err(RESPError::BadBulkStringSize);
+			if bulk_size < 0 || bulk_size as u64 > max_len as u64 {
+				Err(RESPError::BadBulkStringSize)
 			} else {
 				let total_size = pos + bulk_size as usize;
 				if buf.len() < total_size + 2 {
-					return Ok(None);
+					Ok(None)
 				} else {
 					let bulk_string = RedisBufSplit::String(BufSplit(pos, total_size));
-					return Ok(Some((total_size + 2, bulk_string)));
+					Ok(Some((total_size + 2, bulk_string)))
 				}
 			}
 		}
@@ -57,15 +62,20 @@ pub fn bulk_string(buf: &BytesMut, pos: usize) -> RedisResult {
 	}
 }
 
-pub fn array(buf: &BytesMut, pos: usize) -> RedisResult {
+/// `depth` is how many more levels of array nesting are allowed, counting
+/// this one.
+pub fn array(buf: &BytesMut, pos: usize, depth: usize, max_bulk_len: usize) -> RedisResult {
+	if depth == 0 {
+		return Err(RESPError::TooDeeplyNested);
+	}
 	match integer(buf, pos)? {
 		None => Ok(None),
 		Some((pos, -1)) => Ok(Some((pos, RedisBufSplit::NullArray))),
-		Some((pos, num_elements)) if num_elements >= 0 => {
-			let mut values = Vec::with_capacity(num_elements as usize);
+		Some((pos, num_elements)) if (0..=PROTO_MAX_MULTIBULK_LEN).contains(&num_elements) => {
+			let mut values = Vec::with_capacity((num_elements as usize).min(ARRAY_PREALLOC_LIMIT));
 			let mut curr_pos = pos;
 			for _ in 0..num_elements {
-				match parse(buf, curr_pos)? {
+				match parse(buf, curr_pos, depth - 1, max_bulk_len)? {
 					Some((new_pos, redis_value)) => {
 						curr_pos = new_pos;
 						values.push(redis_value)
@@ -86,6 +96,13 @@ pub fn simple_string(buf: &BytesMut, pos: usize) -> RedisResult {
 	}
 }
 
+pub fn error(buf: &BytesMut, pos: usize) -> RedisResult {
+	match word(buf, pos)? {
+		Some((pos, word)) => Ok(Some((pos, RedisBufSplit::Error(word)))),
+		None => Ok(None),
+	}
+}
+
 pub fn resp_int(buf: &BytesMut, pos: usize) -> RedisResult {
 	match integer(buf, pos)? {
 		Some((pos, number)) => Ok(Some((pos, RedisBufSplit::Int(number)))),
@@ -93,18 +110,99 @@ pub fn resp_int(buf: &BytesMut, pos: usize) -> RedisResult {
 	}
 }
 
-pub fn parse(buf: &BytesMut, pos: usize) -> RedisResult {
-	if buf.is_empty() {
+/// Parse one RESP value starting at `pos`, allowing at most `depth` levels
+/// of nested arrays and bulk strings of at most `max_bulk_len` bytes.
+pub fn parse(buf: &BytesMut, pos: usize, depth: usize, max_bulk_len: usize) -> RedisResult {
+	if buf.len() <= pos {
 		return Ok(None);
 	}
 
 	//println!("PARSING DATA: {}", String::from_utf8_lossy(&buf));
 	match buf[pos] {
 		b'+' => simple_string(buf, pos + 1),
-		b'-' => unimplemented!(),
-		b'$' => bulk_string(buf, pos + 1),
+		b'-' => error(buf, pos + 1),
+		b'$' => bulk_string(buf, pos + 1, max_bulk_len),
 		b':' => resp_int(buf, pos + 1),
-		b'*' => array(buf, pos + 1),
+		b'*' => array(buf, pos + 1, depth, max_bulk_len),
 		_ => Err(RESPError::UnknownStartingByte),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::parse;
+	use crate::testing::Session;
+	use crate::types::{ServerState, DEFAULT_PROTO_MAX_BULK_LEN, PROTO_INLINE_MAX_SIZE};
+	use bytes::BytesMut;
+
+	fn nested(levels: usize) -> BytesMut {
+		let mut buf = BytesMut::from("*1\r\n".repeat(levels).as_str());
+		buf.extend_from_slice(b":1\r\n");
+		buf
+	}
+
+	#[test]
+	fn nesting_up_to_the_limit_parses() {
+		let buf = nested(3);
+		let (pos, _) = parse(&buf, 0, 3, DEFAULT_PROTO_MAX_BULK_LEN).unwrap().unwrap();
+		assert_eq!(pos, buf.len());
+		assert!(parse(&buf, 0, 2, DEFAULT_PROTO_MAX_BULK_LEN).is_err());
+	}
+
+	#[test]
+	fn deep_nesting_is_a_protocol_error() {
+		let mut state = ServerState::new();
+		state.max_nesting_depth = 4;
+		let mut session = Session::with_state(state);
+		assert_eq!(
+			session.send(&nested(5)),
+			&b"-ERR Protocol error: too many nested arrays\r\n"[..]
+		);
+		assert!(session.client.is_killed());
+
+		// Far past the default limit, without overflowing the stack.
+		let mut session = Session::new();
+		assert_eq!(
+			session.send(&nested(1_000_000)),
+			&b"-ERR Protocol error: too many nested arrays\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn huge_array_headers_are_refused_without_allocating() {
+		let mut session = Session::new();
+		assert_eq!(
+			session.send(b"*1000000000000\r\n"),
+			&b"-ERR Protocol error: invalid multibulk length\r\n"[..]
+		);
+		assert!(session.client.is_killed());
+
+		// Within the limit, the header just waits for its elements.
+		let mut session = Session::new();
+		assert!(session.send(b"*2147483647\r\n$4\r\nPING\r\n").is_empty());
+		assert!(!session.client.is_killed());
+
+		let mut session = Session::new();
+		assert_eq!(
+			session.send(b"*-2\r\n"),
+			&b"-ERR Protocol error: invalid multibulk length\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn unterminated_lines_are_bounded() {
+		let mut session = Session::new();
+		// Short enough to still be waiting for its CRLF.
+		assert!(session.send(&b"*".repeat(1000)).is_empty());
+		assert!(!session.client.is_killed());
+
+		let mut session = Session::new();
+		let mut line = b"*".to_vec();
+		line.extend(b"1".repeat(PROTO_INLINE_MAX_SIZE + 1));
+		assert_eq!(
+			session.send(&line),
+			&b"-ERR Protocol error: too big inline request\r\n"[..]
+		);
+		assert!(session.client.is_killed());
+	}
+}
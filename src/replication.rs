@@ -0,0 +1,64 @@
+use super::random::random_u64;
+use super::types::*;
+use bytes::Bytes;
+use std::convert::TryFrom;
+
+// Replication isn't implemented: the server is always a standalone master.
+// These commands exist so sentinel-aware clients can probe it without
+// erroring out.
+
+/// `REPLICAOF host port` / `SLAVEOF host port`. Only `NO ONE`, which keeps
+/// the server a master, is accepted.
+pub fn replicaof(a: &[RedisValue]) -> Result<ReturnValue, ReturnError> {
+	let host = Bytes::try_from(a[1].clone())?;
+	let port = Bytes::try_from(a[2].clone())?;
+	if host.eq_ignore_ascii_case(b"no") && port.eq_ignore_ascii_case(b"one") {
+		return Ok(ReturnValue::Ok);
+	}
+	Ok(ReturnValue::Error(Bytes::from(
+		"ERR Replication is not supported by this server",
+	)))
+}
+
+/// `FAILOVER [...]`. There are never replicas to fail over to.
+pub fn failover(_a: &[RedisValue]) -> Result<ReturnValue, ReturnError> {
+	Ok(ReturnValue::Error(Bytes::from(
+		"ERR FAILOVER requires connected replicas.",
+	)))
+}
+
+/// A random 40-character hex id, the format Redis uses for run ids and
+/// replication ids.
+pub fn generate_id() -> String {
+	let mut id = String::with_capacity(40);
+	while id.len() < 40 {
+		id.push_str(&format!("{:016x}", random_u64()));
+	}
+	id.truncate(40);
+	id
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Session;
+
+	#[test]
+	fn replicaof_no_one_is_accepted() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["REPLICAOF", "NO", "ONE"]), b"+OK\r\n");
+		assert_eq!(session.run(&["SLAVEOF", "no", "one"]), b"+OK\r\n");
+	}
+
+	#[test]
+	fn replicaof_a_master_is_refused() {
+		let mut session = Session::new();
+		assert_eq!(
+			session.run(&["REPLICAOF", "127.0.0.1", "6379"]),
+			&b"-ERR Replication is not supported by this server\r\n"[..]
+		);
+		assert_eq!(
+			session.run(&["REPLICAOF", "NO"]),
+			&b"-ERR wrong number of arguments for 'replicaof' command\r\n"[..]
+		);
+	}
+}
@@ -0,0 +1,193 @@
+use super::types::*;
+use bytes::{Bytes, BytesMut};
+
+/// The single RESP2 encoder for everything sent back to clients.
+///
+/// Replies are appended to one growing buffer, so several replies (or a
+/// deeply nested one) are framed without intermediate allocations.
+#[derive(Default)]
+pub struct RespWriter {
+	buf: BytesMut,
+}
+
+impl RespWriter {
+	pub fn new() -> RespWriter {
+		RespWriter::default()
+	}
+
+	/// Encode a single reply.
+	pub fn encode(value: &ReturnValue) -> Bytes {
+		let mut writer = RespWriter::new();
+		writer.write_return_value(value);
+		writer.freeze()
+	}
+
+	pub fn freeze(self) -> Bytes {
+		self.buf.freeze()
+	}
+
+	fn write_line(&mut self, prefix: u8, line: &[u8]) {
+		self.buf.extend_from_slice(&[prefix]);
+		self.buf.extend_from_slice(line);
+		self.buf.extend_from_slice(b"\r\n");
+	}
+
+	/// Simple strings and errors can't carry CR or LF, so any in the
+	/// payload are replaced with spaces, as Redis does.
+	fn write_status(&mut self, prefix: u8, line: &[u8]) {
+		if line.iter().any(|&c| c == b'\r' || c == b'\n') {
+			let sanitized: Vec<u8> = line
+				.iter()
+				.map(|&c| if c == b'\r' || c == b'\n' { b' ' } else { c })
+				.collect();
+			self.write_line(prefix, &sanitized);
+		} else {
+			self.write_line(prefix, line);
+		}
+	}
+
+	pub fn write_simple_string(&mut self, s: &[u8]) {
+		self.write_status(b'+', s);
+	}
+
+	pub fn write_error(&mut self, msg: &[u8]) {
+		self.write_status(b'-', msg);
+	}
+
+	pub fn write_integer(&mut self, i: i64) {
+		self.write_line(b':', i.to_string().as_bytes());
+	}
+
+	/// Bulk strings are binary safe, so the payload is copied through as-is.
+	pub fn write_bulk_string(&mut self, b: &[u8]) {
+		self.write_line(b'$', b.len().to_string().as_bytes());
+		self.buf.extend_from_slice(b);
+		self.buf.extend_from_slice(b"\r\n");
+	}
+
+	pub fn write_null_bulk_string(&mut self) {
+		self.write_line(b'$', b"-1");
+	}
+
+	#[allow(dead_code)]
+	pub fn write_null_array(&mut self) {
+		self.write_line(b'*', b"-1");
+	}
+
+	pub fn write_array_header(&mut self, len: usize) {
+		self.write_line(b'*', len.to_string().as_bytes());
+	}
+
+	pub fn write_return_value(&mut self, value: &ReturnValue) {
+		match value {
+			ReturnValue::Ok => self.write_simple_string(b"OK"),
+			ReturnValue::SimpleString(s) => self.write_simple_string(s),
+			ReturnValue::StringRes(s) => self.write_bulk_string(s),
+			ReturnValue::MultiStringRes(strings) => {
+				self.write_array_header(strings.len());
+				for s in strings {
+					self.write_bulk_string(s);
+				}
+			}
+			ReturnValue::Array(values) => {
+				self.write_array_header(values.len());
+				for value in values {
+					self.write_return_value(value);
+				}
+			}
+			ReturnValue::IntRes(i) => self.write_integer(*i),
+			ReturnValue::Nil => self.write_null_bulk_string(),
+			ReturnValue::Error(msg) => self.write_error(msg),
+		}
+	}
+
+	#[allow(dead_code)]
+	pub fn write_redis_value(&mut self, value: &RedisValue) {
+		match value {
+			RedisValue::String(s) => self.write_bulk_string(s),
+			RedisValue::Error(msg) => self.write_error(msg),
+			RedisValue::ErrorMsg(msg) => self.write_error(msg),
+			RedisValue::Int(i) => self.write_integer(*i),
+			RedisValue::Array(values) => {
+				self.write_array_header(values.len());
+				for value in values {
+					self.write_redis_value(value);
+				}
+			}
+			RedisValue::NullArray => self.write_null_array(),
+			RedisValue::NullBulkString => self.write_null_bulk_string(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::RespWriter;
+	use crate::redis::parse;
+	use crate::types::{RedisValue, DEFAULT_PROTO_MAX_BULK_LEN};
+	use bytes::{Bytes, BytesMut};
+
+	fn encode(value: &RedisValue) -> BytesMut {
+		let mut writer = RespWriter::new();
+		writer.write_redis_value(value);
+		BytesMut::from(&writer.freeze()[..])
+	}
+
+	fn round_trip(value: RedisValue) -> RedisValue {
+		let buf = encode(&value);
+		let parsed = parse(&buf, 0, 8, DEFAULT_PROTO_MAX_BULK_LEN).unwrap();
+		let (pos, split) = parsed.expect("complete frame");
+		assert_eq!(pos, buf.len());
+		split.redis_value(&buf.freeze())
+	}
+
+	#[test]
+	fn strings_round_trip_as_bulk_strings() {
+		let value = RedisValue::String(Bytes::from(&b"a\r\nb\x00"[..]));
+		assert_eq!(&encode(&value)[..], b"$5\r\na\r\nb\x00\r\n");
+		assert!(round_trip(value.clone()) == value);
+	}
+
+	#[test]
+	fn errors_round_trip() {
+		let value = RedisValue::Error(Bytes::from("ERR boom"));
+		assert_eq!(&encode(&value)[..], b"-ERR boom\r\n");
+		assert!(round_trip(value.clone()) == value);
+		// An error message encodes the same way, so reads back as an error.
+		let message = RedisValue::ErrorMsg(b"ERR boom".to_vec());
+		assert!(round_trip(message) == value);
+	}
+
+	#[test]
+	fn integers_round_trip() {
+		for i in &[0, -1, i64::MAX, i64::MIN] {
+			let value = RedisValue::Int(*i);
+			assert_eq!(&encode(&value)[..], format!(":{}\r\n", i).as_bytes());
+			assert!(round_trip(value.clone()) == value);
+		}
+	}
+
+	#[test]
+	fn nested_arrays_round_trip() {
+		let value = RedisValue::Array(vec![
+			RedisValue::String(Bytes::from("x")),
+			RedisValue::Array(vec![RedisValue::Int(1), RedisValue::NullArray]),
+			RedisValue::Array(vec![]),
+		]);
+		assert_eq!(&encode(&value)[..], &b"*3\r\n$1\r\nx\r\n*2\r\n:1\r\n*-1\r\n*0\r\n"[..]);
+		assert!(round_trip(value.clone()) == value);
+	}
+
+	#[test]
+	fn null_array_round_trips() {
+		assert_eq!(&encode(&RedisValue::NullArray)[..], b"*-1\r\n");
+		assert!(round_trip(RedisValue::NullArray) == RedisValue::NullArray);
+	}
+
+	#[test]
+	fn null_bulk_string_is_encoded_but_not_accepted_in_requests() {
+		let buf = encode(&RedisValue::NullBulkString);
+		assert_eq!(&buf[..], b"$-1\r\n");
+		assert!(parse(&buf, 0, 8, DEFAULT_PROTO_MAX_BULK_LEN).is_err());
+	}
+}
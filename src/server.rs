@@ -0,0 +1,283 @@
+use super::commands::lookup_command;
+use super::redis::*;
+use super::resp::RespWriter;
+use super::types::*;
+use bytes::buf::BufMutExt;
+use bytes::{Bytes, BytesMut};
+use std::io::{Error, Read, Write};
+use std::time::Instant;
+
+/// Largest output buffer kept between reads.
+const OUTPUT_BUFFER_RETAIN: usize = 64 * 1024;
+
+/// The command name a request starts with, as sent.
+fn command_head(redis_value: &RedisValue) -> Option<&Bytes> {
+	match redis_value {
+		RedisValue::String(head) => Some(head),
+		RedisValue::Array(values) => match values.first() {
+			Some(RedisValue::String(head)) => Some(head),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// The command table name for a request, used to key per-command stats.
+fn command_name(redis_value: &RedisValue) -> Option<&'static str> {
+	let head = command_head(redis_value)?;
+	lookup_command(&String::from_utf8_lossy(head)).map(|spec| spec.name)
+}
+
+/// Run a single command and write its reply.
+///
+/// Only `Write` is needed, so an in-memory buffer such as a `Vec<u8>` can
+/// stand in for a socket.
+pub fn handle_command<W: Write>(
+	stream: &mut W,
+	redis_value: RedisValue,
+	state: &mut State,
+	client: &mut Client,
+) -> Result<(), Error> {
+	let started = Instant::now();
+	let result = ReturnValue::parse_redis_value(&redis_value, state, client);
+	let elapsed = started.elapsed();
+	if let Some(name) = command_name(&redis_value) {
+		state.record_command(name, elapsed);
+	}
+	// A negative threshold disables the slow log.
+	let usec = elapsed.as_micros() as u64;
+	let threshold = state.slowlog_log_slower_than;
+	if threshold >= 0 && usec >= threshold as u64 {
+		let mut slowlog = state.slowlog.lock().unwrap();
+		slowlog.record(&redis_value, usec, &client.addr, state.slowlog_max_len);
+	}
+	let head = command_head(&redis_value).cloned().unwrap_or_default();
+	match result {
+		Ok(value) => stream.write_all(&RespWriter::encode(&value)),
+		Err(ReturnError::WrongType) => stream.write_all(&RespWriter::encode(&wrong_type())),
+		Err(ReturnError::UnknownType) => {
+			stream.write_all(&RespWriter::encode(&unknown_command(&head)))
+		}
+	}
+}
+
+/// Run every complete command in `buf`, in order, leaving any trailing
+/// partial command for the next read.
+///
+/// Each reply is written before the next command is parsed, so pipelined
+/// replies always come back in request order. A malformed command gets a
+/// protocol error reply and marks the client for closing. Fails only when
+/// writing a reply fails, in which case the connection should be dropped.
+pub fn handle_message<W: Write>(
+	stream: &mut W,
+	buf: &mut BytesMut,
+	state: &mut State,
+	client: &mut Client,
+) -> Result<(), Error> {
+	loop {
+		match parse(buf, 0, state.max_nesting_depth, state.proto_max_bulk_len) {
+			Ok(Some((pos, value))) => {
+				let data = buf.split_to(pos);
+				let redis_value = value.redis_value(&data.freeze());
+				log_debug!("Received command:\n{}", redis_value);
+				handle_command(stream, redis_value, state, client)?
+			}
+			Ok(None) => return Ok(()),
+			Err(e) => {
+				// There's no telling where the next command starts, so as
+				// in Redis the client is told why and disconnected.
+				log_warn!("Error parsing: {}", e);
+				let reply = ReturnValue::Error(Bytes::from(format!(
+					"ERR Protocol error: {}",
+					e.protocol_message()
+				)));
+				stream.write_all(&RespWriter::encode(&reply))?;
+				buf.clear();
+				client.close();
+				return Ok(());
+			}
+		}
+	}
+}
+
+/// Turn away a connection accepted while every worker is taken. The
+/// stream is closed once it is dropped.
+pub fn refuse_client<W: Write>(mut stream: W) {
+	let reply = ReturnValue::Error(Bytes::from("ERR max number of clients reached"));
+	if let Err(error) = stream.write_all(&RespWriter::encode(&reply)) {
+		log_debug!("failed to refuse client: {:?}", error);
+	}
+}
+
+/// Serve one connection until it closes or is killed.
+///
+/// `shutdown` is called when another connection kills this one, and must
+/// make a read blocked on `stream` return.
+pub fn handle_client<S: Read + Write>(
+	stream: S,
+	peer: String,
+	shutdown: Box<dyn Fn() + Send>,
+	state: &mut State,
+) -> Result<(), Error> {
+	log_info!("Incoming connection from: {}", peer);
+	let mut client = state.register_client(peer, shutdown);
+	let result = serve_client(stream, state, &mut client);
+	state.unregister_client(client.id);
+	result
+}
+
+fn serve_client<S: Read + Write>(
+	mut stream: S,
+	state: &mut State,
+	client: &mut Client,
+) -> Result<(), Error> {
+	let mut temp_buf = [0; MESSAGE_SIZE];
+	let mut buf = BytesMut::with_capacity(MESSAGE_SIZE);
+	// Replies to everything in one read are gathered here and sent with a
+	// single write, rather than one syscall per pipelined command.
+	let mut out = BytesMut::with_capacity(MESSAGE_SIZE);
+
+	loop {
+		let bytes_read = stream.read(&mut temp_buf)?;
+		// A zero-length read is always EOF: the peer has closed, and
+		// anything left in `buf` is a partial command that can't complete.
+		if bytes_read == 0 || client.is_killed() {
+			return Ok(());
+		}
+		buf.extend_from_slice(&temp_buf[..bytes_read]);
+		handle_message(&mut (&mut out).writer(), &mut buf, state, client)?;
+		stream.write_all(&out)?;
+		out.clear();
+		// Don't hold on to the memory of one oversized reply.
+		if out.capacity() > OUTPUT_BUFFER_RETAIN {
+			out = BytesMut::with_capacity(MESSAGE_SIZE);
+		}
+		if client.is_killed() {
+			return Ok(());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{handle_client, handle_message};
+	use crate::testing::{MockStream, Session};
+	use crate::types::ServerState;
+	use std::io::{Read, Write};
+	use std::os::unix::net::{UnixListener, UnixStream};
+	use std::sync::Arc;
+	use std::thread;
+
+	#[test]
+	fn serves_a_unix_socket_connection() {
+		let path = std::env::temp_dir().join(format!("copyless-redis-{}.sock", std::process::id()));
+		let _ = std::fs::remove_file(&path);
+		let listener = UnixListener::bind(&path).unwrap();
+		let mut state = Arc::new(ServerState::new());
+		let server = thread::spawn(move || {
+			let (stream, _) = listener.accept().unwrap();
+			handle_client(stream, "unix".to_string(), Box::new(|| {}), &mut state)
+		});
+
+		let mut client = UnixStream::connect(&path).unwrap();
+		client.write_all(b"*1\r\n$4\r\nPING\r\n").unwrap();
+		let mut reply = [0; 7];
+		client.read_exact(&mut reply).unwrap();
+		assert_eq!(&reply, b"+PONG\r\n");
+		drop(client);
+		server.join().unwrap().unwrap();
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn replies_into_an_in_memory_writer() {
+		let mut session = Session::new();
+		let mut out: Vec<u8> = Vec::new();
+		let mut buf = bytes::BytesMut::from(&b"*1\r\n$4\r\nPING\r\n"[..]);
+		handle_message(&mut out, &mut buf, &mut session.state, &mut session.client).unwrap();
+		assert_eq!(out, b"+PONG\r\n");
+		assert!(buf.is_empty());
+	}
+
+	#[test]
+	fn short_writes_still_deliver_every_reply() {
+		let input = b"*2\r\n$4\r\nECHO\r\n$20\r\nabcdefghijklmnopqrst\r\n*1\r\n$4\r\nPING\r\n";
+		let mut stream = MockStream::new(vec![input.to_vec()]);
+		stream.max_write = 3;
+		let mut state = Arc::new(ServerState::new());
+		handle_client(&mut stream, "mock".to_string(), Box::new(|| {}), &mut state).unwrap();
+		assert_eq!(stream.output, b"$20\r\nabcdefghijklmnopqrst\r\n+PONG\r\n");
+		assert!(stream.writes > 1);
+	}
+
+	#[test]
+	fn unknown_commands_get_an_error_reply() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["NOSUCHCMD", "a"]), b"-ERR unknown command 'NOSUCHCMD'\r\n");
+		assert_eq!(session.run(&["PING"]), b"+PONG\r\n");
+	}
+
+	#[test]
+	fn oversized_bulk_headers_are_protocol_errors() {
+		let mut session = Session::new();
+		assert_eq!(
+			session.send(b"*1\r\n$99999999999\r\n"),
+			&b"-ERR Protocol error: invalid bulk length\r\n"[..]
+		);
+		assert!(session.client.is_killed());
+
+		let mut state = ServerState::new();
+		state.proto_max_bulk_len = 4;
+		let mut session = Session::with_state(state);
+		assert_eq!(session.run(&["ECHO", "abcd"]), b"$4\r\nabcd\r\n");
+		assert_eq!(
+			session.run(&["ECHO", "abcde"]),
+			&b"-ERR Protocol error: invalid bulk length\r\n"[..]
+		);
+	}
+
+	fn serve(stream: &mut MockStream) {
+		let mut state = Arc::new(ServerState::new());
+		handle_client(&mut *stream, "mock".to_string(), Box::new(|| {}), &mut state).unwrap();
+		assert!(state.clients.lock().unwrap().is_empty());
+	}
+
+	#[test]
+	fn eof_ends_the_connection_quietly() {
+		let mut stream = MockStream::new(vec![]);
+		serve(&mut stream);
+		assert_eq!(stream.writes, 0);
+
+		// A command cut off by EOF is dropped without a reply.
+		let mut stream = MockStream::new(vec![b"*2\r\n$3\r\nGET\r\n$3\r\nke".to_vec()]);
+		serve(&mut stream);
+		assert_eq!(stream.reads, 1);
+		assert!(stream.output.is_empty());
+	}
+
+	#[test]
+	fn pipelined_replies_go_out_in_one_write_per_read() {
+		let mut stream = MockStream::new(vec![b"*1\r\n$4\r\nPING\r\n".repeat(100)]);
+		serve(&mut stream);
+		// 1400 bytes arrive in two reads of at most MESSAGE_SIZE.
+		assert_eq!(stream.reads, 2);
+		assert_eq!(stream.writes, 2);
+		assert_eq!(stream.output, b"+PONG\r\n".repeat(100));
+	}
+
+	#[test]
+	fn non_array_requests_are_refused() {
+		let mut session = Session::new();
+		let expected = &b"-ERR Protocol error: expected array\r\n"[..];
+		assert_eq!(session.send(b":5\r\n"), expected);
+		assert_eq!(session.send(b"*-1\r\n"), expected);
+		assert_eq!(session.run(&["PING"]), b"+PONG\r\n");
+	}
+
+	#[test]
+	fn refused_clients_are_told_why() {
+		let mut stream = MockStream::new(vec![]);
+		super::refuse_client(&mut stream);
+		assert_eq!(stream.output, b"-ERR max number of clients reached\r\n");
+	}
+}
@@ -0,0 +1,208 @@
+use super::types::*;
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default `slowlog-log-slower-than`, in microseconds.
+pub const DEFAULT_SLOWLOG_LOG_SLOWER_THAN: i64 = 10_000;
+/// Default `slowlog-max-len`.
+pub const DEFAULT_SLOWLOG_MAX_LEN: usize = 128;
+
+/// As in Redis, only this many arguments of a command are kept, with the
+/// last slot saying how many were dropped...
+const SLOWLOG_ENTRY_MAX_ARGC: usize = 32;
+/// ...and each kept argument is cut to this many bytes.
+const SLOWLOG_ENTRY_MAX_STRING: usize = 128;
+
+const SLOWLOG_HELP: &[&str] = &[
+	"GET [<count>]",
+	"    Return top <count> entries from the slowlog (default: 10, -1 mean all).",
+	"    Entries are made of:",
+	"    id, timestamp, time in microseconds, arguments array, client IP and port,",
+	"    client name",
+	"LEN",
+	"    Return the length of the slowlog.",
+	"RESET",
+	"    Reset the slowlog.",
+];
+
+pub struct SlowLogEntry {
+	id: u64,
+	/// Unix time the command ran at, in seconds.
+	timestamp: u64,
+	duration_usec: u64,
+	args: Vec<Bytes>,
+	addr: String,
+}
+
+/// The most recent slow commands, newest first, bounded by
+/// `slowlog-max-len`.
+#[derive(Default)]
+pub struct SlowLog {
+	entries: VecDeque<SlowLogEntry>,
+	next_id: u64,
+}
+
+impl SlowLog {
+	pub fn new() -> SlowLog {
+		SlowLog::default()
+	}
+
+	/// Log `request`, which took `duration_usec` microseconds, keeping the
+	/// newest `max_len` entries. The caller checks it against the threshold,
+	/// so the log is only locked for commands that get an entry.
+	pub fn record(&mut self, request: &RedisValue, duration_usec: u64, addr: &str, max_len: usize) {
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_secs())
+			.unwrap_or(0);
+		self.entries.push_front(SlowLogEntry {
+			id: self.next_id,
+			timestamp,
+			duration_usec,
+			args: entry_args(request),
+			addr: addr.to_string(),
+		});
+		self.next_id += 1;
+		self.entries.truncate(max_len);
+	}
+}
+
+/// The arguments of `request` as stored in an entry, trimmed the way Redis
+/// trims them.
+fn entry_args(request: &RedisValue) -> Vec<Bytes> {
+	let values = match request {
+		RedisValue::Array(values) => &values[..],
+		other => std::slice::from_ref(other),
+	};
+	let kept = if values.len() > SLOWLOG_ENTRY_MAX_ARGC {
+		SLOWLOG_ENTRY_MAX_ARGC - 1
+	} else {
+		values.len()
+	};
+	let mut args: Vec<Bytes> = values[..kept]
+		.iter()
+		.map(|value| match value {
+			RedisValue::String(arg) if arg.len() > SLOWLOG_ENTRY_MAX_STRING => {
+				let mut cut = BytesMut::with_capacity(SLOWLOG_ENTRY_MAX_STRING + 32);
+				cut.put_slice(&arg[..SLOWLOG_ENTRY_MAX_STRING]);
+				cut.put_slice(
+					format!("... ({} more bytes)", arg.len() - SLOWLOG_ENTRY_MAX_STRING).as_bytes(),
+				);
+				cut.freeze()
+			}
+			RedisValue::String(arg) => arg.clone(),
+			RedisValue::Int(n) => Bytes::from(n.to_string()),
+			_ => Bytes::new(),
+		})
+		.collect();
+	if kept < values.len() {
+		args.push(Bytes::from(format!(
+			"... ({} more arguments)",
+			values.len() - kept
+		)));
+	}
+	args
+}
+
+/// `SLOWLOG <subcommand> [arg ...]`
+pub fn slowlog(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let subcommand = Bytes::try_from(a[1].clone())?;
+	match subcommand.to_ascii_lowercase().as_slice() {
+		b"help" => Ok(subcommand_help("SLOWLOG", SLOWLOG_HELP)),
+		b"len" => Ok(ReturnValue::IntRes(
+			state.slowlog.lock().unwrap().entries.len() as i64,
+		)),
+		b"reset" => {
+			state.slowlog.lock().unwrap().entries.clear();
+			Ok(ReturnValue::Ok)
+		}
+		b"get" => {
+			if a.len() > 3 {
+				return Ok(wrong_number_of_arguments("slowlog|get"));
+			}
+			let count = match a.get(2).map(integer_arg) {
+				None => 10,
+				Some(Some(-1)) => usize::MAX,
+				Some(Some(n)) if n >= 0 => n as usize,
+				Some(_) => {
+					return Ok(ReturnValue::Error(Bytes::from(
+						"ERR count should be greater than or equal to -1",
+					)))
+				}
+			};
+			let log = state.slowlog.lock().unwrap();
+			let entries = log
+				.entries
+				.iter()
+				.take(count)
+				.map(|entry| {
+					ReturnValue::Array(vec![
+						ReturnValue::IntRes(entry.id as i64),
+						ReturnValue::IntRes(entry.timestamp as i64),
+						ReturnValue::IntRes(entry.duration_usec as i64),
+						ReturnValue::MultiStringRes(entry.args.clone()),
+						ReturnValue::StringRes(Bytes::from(entry.addr.clone())),
+						ReturnValue::StringRes(Bytes::new()),
+					])
+				})
+				.collect();
+			Ok(ReturnValue::Array(entries))
+		}
+		_ => Ok(unknown_subcommand("SLOWLOG", &subcommand)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Session;
+	use crate::types::ServerState;
+
+	fn logging_everything(max_len: usize) -> Session {
+		let mut state = ServerState::new();
+		state.slowlog_log_slower_than = 0;
+		state.slowlog_max_len = max_len;
+		Session::with_state(state)
+	}
+
+	fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+		haystack.windows(needle.len()).any(|window| window == needle)
+	}
+
+	#[test]
+	fn threshold_zero_logs_every_command() {
+		let mut session = logging_everything(128);
+		session.run(&["SET", "k", "v"]);
+		session.run(&["GET", "k"]);
+		let newest = session.run(&["SLOWLOG", "GET", "1"]);
+		// Ids count from 0, so GET is the second entry.
+		assert!(newest.starts_with(b"*1\r\n*6\r\n:1\r\n:"));
+		let tail = b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n$14\r\n127.0.0.1:6380\r\n$0\r\n\r\n";
+		assert!(newest.ends_with(tail), "{:?}", String::from_utf8_lossy(&newest));
+		// SLOWLOG GET is logged too.
+		assert_eq!(session.run(&["SLOWLOG", "LEN"]), b":3\r\n");
+	}
+
+	#[test]
+	fn the_log_keeps_only_the_newest_entries() {
+		let mut session = logging_everything(2);
+		for _ in 0..5 {
+			session.run(&["PING"]);
+		}
+		assert_eq!(session.run(&["SLOWLOG", "LEN"]), b":2\r\n");
+		assert_eq!(session.run(&["SLOWLOG", "RESET"]), b"+OK\r\n");
+		// RESET is itself logged once it has run.
+		assert_eq!(session.run(&["SLOWLOG", "LEN"]), b":1\r\n");
+	}
+
+	#[test]
+	fn long_arguments_are_trimmed() {
+		let mut session = logging_everything(128);
+		session.run(&["ECHO", &"x".repeat(200)]);
+		let entry = session.run(&["SLOWLOG", "GET", "1"]);
+		let trimmed = format!("{}... (72 more bytes)", "x".repeat(128));
+		let expected = format!("${}\r\n{}\r\n", trimmed.len(), trimmed);
+		assert!(contains(&entry, expected.as_bytes()), "{:?}", String::from_utf8_lossy(&entry));
+	}
+}
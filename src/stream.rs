@@ -0,0 +1,481 @@
+use super::db::now_ms;
+use super::types::*;
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str;
+
+/// A stream entry id: a millisecond timestamp and a sequence number within
+/// that millisecond. Ids order by `ms`, then `seq`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StreamId {
+	pub ms: u64,
+	pub seq: u64,
+}
+
+impl fmt::Display for StreamId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}-{}", self.ms, self.seq)
+	}
+}
+
+/// The id argument of `XADD`.
+#[derive(Clone, Copy)]
+pub enum IdSpec {
+	/// `*`: both parts generated.
+	Auto,
+	/// `ms-*`: the sequence number generated.
+	AutoSeq(u64),
+	Explicit(StreamId),
+}
+
+/// An append-only log of entries, each a list of field/value pairs, kept in
+/// id order.
+#[derive(Default)]
+pub struct Stream {
+	entries: BTreeMap<StreamId, Vec<(Bytes, Bytes)>>,
+	/// The largest id ever added. Kept apart from `entries` so trimming
+	/// never lets an id be reused.
+	last_id: StreamId,
+}
+
+impl Stream {
+	pub fn new() -> Stream {
+		Stream::default()
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	#[allow(dead_code)]
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&StreamId, &Vec<(Bytes, Bytes)>)> {
+		self.entries.iter()
+	}
+
+	/// The id `spec` resolves to, which must be greater than every id in the
+	/// stream so far.
+	fn next_id(&self, spec: IdSpec) -> Result<StreamId, &'static str> {
+		let last = self.last_id;
+		let id = match spec {
+			IdSpec::Auto => {
+				let ms = now_ms();
+				if ms > last.ms {
+					StreamId { ms, seq: 0 }
+				} else if last.seq < u64::MAX {
+					// The clock hasn't moved past the last id (or went back).
+					StreamId { ms: last.ms, seq: last.seq + 1 }
+				} else if last.ms < u64::MAX {
+					StreamId { ms: last.ms + 1, seq: 0 }
+				} else {
+					return Err("ERR The stream has exhausted the last possible ID, unable to add more items");
+				}
+			}
+			IdSpec::AutoSeq(ms) if ms == last.ms && last.seq < u64::MAX => StreamId { ms, seq: last.seq + 1 },
+			IdSpec::AutoSeq(ms) if ms > last.ms => StreamId { ms, seq: 0 },
+			IdSpec::AutoSeq(_) => return Err(ID_TOO_SMALL),
+			IdSpec::Explicit(id) if id == StreamId::default() => {
+				return Err("ERR The ID specified in XADD must be greater than 0-0")
+			}
+			IdSpec::Explicit(id) if id <= last => return Err(ID_TOO_SMALL),
+			IdSpec::Explicit(id) => id,
+		};
+		Ok(id)
+	}
+
+	/// Append an entry, returning the id it was given.
+	pub fn add(&mut self, spec: IdSpec, fields: Vec<(Bytes, Bytes)>) -> Result<StreamId, &'static str> {
+		let id = self.next_id(spec)?;
+		self.entries.insert(id, fields);
+		self.last_id = id;
+		Ok(id)
+	}
+
+	/// Entries with ids from `start` to `end`, inclusive, in id order.
+	/// `start` must not be after `end`.
+	pub fn range(
+		&self,
+		start: StreamId,
+		end: StreamId,
+	) -> impl DoubleEndedIterator<Item = (&StreamId, &Vec<(Bytes, Bytes)>)> {
+		self.entries.range(start..=end)
+	}
+
+	/// Drop the oldest entries until at most `max_len` are left.
+	pub fn trim(&mut self, max_len: usize) {
+		while self.entries.len() > max_len {
+			let oldest = *self.entries.keys().next().unwrap();
+			self.entries.remove(&oldest);
+		}
+	}
+}
+
+const ID_TOO_SMALL: &str = "ERR The ID specified in XADD is equal or smaller than the target stream top item";
+const INVALID_ID: &str = "ERR Invalid stream ID specified as stream command argument";
+
+/// Parse the id argument of `XADD`: `*`, `ms-*`, `ms-seq`, or a bare `ms`
+/// meaning `ms-0`.
+fn parse_id_spec(arg: &[u8]) -> Option<IdSpec> {
+	if arg == b"*" {
+		return Some(IdSpec::Auto);
+	}
+	let arg = str::from_utf8(arg).ok()?;
+	let (ms, seq) = match arg.find('-') {
+		Some(dash) => (&arg[..dash], Some(&arg[dash + 1..])),
+		None => (arg, None),
+	};
+	let ms = ms.parse::<u64>().ok()?;
+	match seq {
+		None => Some(IdSpec::Explicit(StreamId { ms, seq: 0 })),
+		Some("*") => Some(IdSpec::AutoSeq(ms)),
+		Some(seq) => Some(IdSpec::Explicit(StreamId {
+			ms,
+			seq: seq.parse::<u64>().ok()?,
+		})),
+	}
+}
+
+impl StreamId {
+	const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+	const MAX: StreamId = StreamId {
+		ms: u64::MAX,
+		seq: u64::MAX,
+	};
+
+	fn next(self) -> Option<StreamId> {
+		match self.seq.checked_add(1) {
+			Some(seq) => Some(StreamId { ms: self.ms, seq }),
+			None => Some(StreamId {
+				ms: self.ms.checked_add(1)?,
+				seq: 0,
+			}),
+		}
+	}
+
+	fn prev(self) -> Option<StreamId> {
+		match self.seq.checked_sub(1) {
+			Some(seq) => Some(StreamId { ms: self.ms, seq }),
+			None => Some(StreamId {
+				ms: self.ms.checked_sub(1)?,
+				seq: u64::MAX,
+			}),
+		}
+	}
+}
+
+/// One end of an `XRANGE` interval.
+enum Bound {
+	Inclusive(StreamId),
+	Exclusive(StreamId),
+}
+
+/// Parse an interval end: `-` or `+`, `ms-seq`, a bare `ms` (taking
+/// `missing_seq` as the sequence number), or either id form prefixed with
+/// `(` to exclude it.
+fn parse_bound(arg: &[u8], missing_seq: u64) -> Option<Bound> {
+	match arg {
+		b"-" => return Some(Bound::Inclusive(StreamId::MIN)),
+		b"+" => return Some(Bound::Inclusive(StreamId::MAX)),
+		_ => {}
+	}
+	let (exclusive, arg) = match arg.split_first() {
+		Some((b'(', rest)) if !rest.is_empty() => (true, rest),
+		_ => (false, arg),
+	};
+	let arg = str::from_utf8(arg).ok()?;
+	let (ms, seq) = match arg.find('-') {
+		Some(dash) => (&arg[..dash], arg[dash + 1..].parse::<u64>().ok()?),
+		None => (arg, missing_seq),
+	};
+	let id = StreamId {
+		ms: ms.parse::<u64>().ok()?,
+		seq,
+	};
+	Some(if exclusive { Bound::Exclusive(id) } else { Bound::Inclusive(id) })
+}
+
+/// `XADD key [NOMKSTREAM] [MAXLEN [= | ~] threshold] <* | id> field value
+/// [field value ...]`
+///
+/// `~` is accepted, but trimming is always exact.
+pub fn xadd(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let (mut no_mkstream, mut max_len) = (false, None);
+	let mut i = 2;
+	let spec = loop {
+		if i >= a.len() {
+			return Ok(wrong_number_of_arguments("xadd"));
+		}
+		let arg = Bytes::try_from(a[i].clone())?;
+		match arg.to_ascii_lowercase().as_slice() {
+			b"nomkstream" => no_mkstream = true,
+			b"maxlen" if i + 1 < a.len() => {
+				if matches!(&a[i + 1], RedisValue::String(s) if &s[..] == b"=" || &s[..] == b"~") {
+					i += 1;
+				}
+				max_len = match a.get(i + 1).map(integer_arg) {
+					Some(Some(n)) if n >= 0 => Some(n as usize),
+					Some(Some(_)) => {
+						return Ok(ReturnValue::Error(Bytes::from(
+							"ERR The MAXLEN argument must be >= 0.",
+						)))
+					}
+					Some(None) => return Ok(not_an_integer()),
+					None => return Ok(syntax_error()),
+				};
+				i += 1;
+			}
+			_ => match parse_id_spec(&arg) {
+				Some(spec) => break spec,
+				None => return Ok(ReturnValue::Error(Bytes::from(INVALID_ID))),
+			},
+		}
+		i += 1;
+	};
+
+	let pairs = &a[i + 1..];
+	if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+		return Ok(wrong_number_of_arguments("xadd"));
+	}
+	let mut fields = Vec::with_capacity(pairs.len() / 2);
+	for pair in pairs.chunks(2) {
+		fields.push((Bytes::try_from(pair[0].clone())?, Bytes::try_from(pair[1].clone())?));
+	}
+
+	let mut db = state.db.lock().unwrap();
+	let added = match db.get_stream_mut(&key)? {
+		Some(stream) => {
+			let added = stream.add(spec, fields);
+			if let (Ok(_), Some(max_len)) = (&added, max_len) {
+				stream.trim(max_len);
+			}
+			added
+		}
+		None if no_mkstream => return Ok(ReturnValue::Nil),
+		None => {
+			let mut stream = Stream::new();
+			let added = stream.add(spec, fields);
+			// A rejected id leaves the key absent.
+			if added.is_ok() {
+				if let Some(max_len) = max_len {
+					stream.trim(max_len);
+				}
+				db.insert(key, stream);
+			}
+			added
+		}
+	};
+	Ok(match added {
+		Ok(id) => ReturnValue::StringRes(Bytes::from(id.to_string())),
+		Err(msg) => ReturnValue::Error(Bytes::from(msg)),
+	})
+}
+
+/// `XLEN key`
+pub fn xlen(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let len = match state.db.lock().unwrap().get_stream(&key)? {
+		Some(stream) => stream.len(),
+		None => 0,
+	};
+	Ok(ReturnValue::IntRes(len as i64))
+}
+
+/// `XRANGE key start end [COUNT count]`
+pub fn xrange(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	range_command(a, state, false)
+}
+
+/// `XREVRANGE key end start [COUNT count]`
+pub fn xrevrange(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	range_command(a, state, true)
+}
+
+/// Shared by `XRANGE` and `XREVRANGE`, which takes its bounds the other
+/// way round and replies newest first.
+fn range_command(a: &[RedisValue], state: &State, rev: bool) -> Result<ReturnValue, ReturnError> {
+	// The table requires at least the key and both bounds; only COUNT
+	// may follow them.
+	if a.len() != 4 && a.len() != 6 {
+		return Ok(syntax_error());
+	}
+	let key = Bytes::try_from(a[1].clone())?;
+	let (start_arg, end_arg) = if rev { (&a[3], &a[2]) } else { (&a[2], &a[3]) };
+	let start_arg = Bytes::try_from(start_arg.clone())?;
+	let end_arg = Bytes::try_from(end_arg.clone())?;
+	let start = match parse_bound(&start_arg, 0) {
+		Some(Bound::Inclusive(id)) => Some(id),
+		Some(Bound::Exclusive(id)) => id.next(),
+		None => return Ok(ReturnValue::Error(Bytes::from(INVALID_ID))),
+	};
+	let end = match parse_bound(&end_arg, u64::MAX) {
+		Some(Bound::Inclusive(id)) => Some(id),
+		Some(Bound::Exclusive(id)) => id.prev(),
+		None => return Ok(ReturnValue::Error(Bytes::from(INVALID_ID))),
+	};
+	let count = match a.get(4) {
+		None => usize::MAX,
+		Some(option) => {
+			if Bytes::try_from(option.clone())?.to_ascii_lowercase() != b"count" {
+				return Ok(syntax_error());
+			}
+			match integer_arg(&a[5]) {
+				Some(n) => n.max(0) as usize,
+				None => return Ok(not_an_integer()),
+			}
+		}
+	};
+	let (start, end) = match (start, end) {
+		(Some(start), Some(end)) => (start, end),
+		(None, _) => {
+			return Ok(ReturnValue::Error(Bytes::from(
+				"ERR invalid start ID for the interval",
+			)))
+		}
+		(_, None) => {
+			return Ok(ReturnValue::Error(Bytes::from(
+				"ERR invalid end ID for the interval",
+			)))
+		}
+	};
+
+	let mut db = state.db.lock().unwrap();
+	let stream = match db.get_stream(&key)? {
+		Some(stream) if start <= end => stream,
+		_ => return Ok(ReturnValue::Array(vec![])),
+	};
+	let entry = |(id, fields): (&StreamId, &Vec<(Bytes, Bytes)>)| {
+		ReturnValue::Array(vec![
+			ReturnValue::StringRes(Bytes::from(id.to_string())),
+			ReturnValue::MultiStringRes(
+				fields
+					.iter()
+					.flat_map(|(field, value)| vec![field.clone(), value.clone()])
+					.collect(),
+			),
+		])
+	};
+	let entries = if rev {
+		stream.range(start, end).rev().take(count).map(entry).collect()
+	} else {
+		stream.range(start, end).take(count).map(entry).collect()
+	};
+	Ok(ReturnValue::Array(entries))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::StreamId;
+	use crate::testing::Session;
+
+	fn bulk(reply: &[u8]) -> String {
+		let text = String::from_utf8(reply.to_vec()).unwrap();
+		let mut lines = text.split("\r\n");
+		assert!(lines.next().unwrap().starts_with('$'), "{}", text);
+		lines.next().unwrap().to_string()
+	}
+
+	fn id(text: &str) -> StreamId {
+		let (ms, seq) = text.split_once('-').unwrap();
+		StreamId {
+			ms: ms.parse().unwrap(),
+			seq: seq.parse().unwrap(),
+		}
+	}
+
+	#[test]
+	fn auto_ids_strictly_increase() {
+		let mut session = Session::new();
+		let mut previous = StreamId::default();
+		for _ in 0..50 {
+			let next = id(&bulk(&session.run(&["XADD", "s", "*", "f", "v"])));
+			assert!(next > previous, "{} after {}", next, previous);
+			previous = next;
+		}
+		assert_eq!(session.run(&["XLEN", "s"]), b":50\r\n");
+		let same_ms = format!("{}-*", previous.ms);
+		let next = id(&bulk(&session.run(&["XADD", "s", &same_ms, "f", "v"])));
+		assert_eq!(next, StreamId { ms: previous.ms, seq: previous.seq + 1 });
+	}
+
+	#[test]
+	fn xadd_refuses_ids_at_or_below_the_top() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["XADD", "s", "5-1", "f", "v"]), b"$3\r\n5-1\r\n");
+		let too_small = format!("-{}\r\n", super::ID_TOO_SMALL).into_bytes();
+		assert_eq!(session.run(&["XADD", "s", "5-1", "f", "v"]), too_small);
+		assert_eq!(session.run(&["XADD", "s", "4-9", "f", "v"]), too_small);
+		assert_eq!(session.run(&["XADD", "s", "5-*", "f", "v"]), b"$3\r\n5-2\r\n");
+		assert_eq!(session.run(&["XLEN", "s"]), b":2\r\n");
+		assert_eq!(session.run(&["XLEN", "missing"]), b":0\r\n");
+		assert_eq!(
+			session.run(&["XADD", "fresh", "0-0", "f", "v"]),
+			&b"-ERR The ID specified in XADD must be greater than 0-0\r\n"[..]
+		);
+		assert_eq!(session.run(&["EXISTS", "fresh"]), b":0\r\n");
+	}
+
+	fn entry(id: &str, value: &str) -> String {
+		let id = format!("${}\r\n{}\r\n", id.len(), id);
+		format!("*2\r\n{}*2\r\n$1\r\nf\r\n${}\r\n{}\r\n", id, value.len(), value)
+	}
+
+	fn entries(items: &[(&str, &str)]) -> Vec<u8> {
+		let mut out = format!("*{}\r\n", items.len());
+		for (id, value) in items {
+			out.push_str(&entry(id, value));
+		}
+		out.into_bytes()
+	}
+
+	fn three_entries() -> Session {
+		let mut session = Session::new();
+		for (id, value) in &[("1-1", "a"), ("2-1", "b"), ("3-1", "c")] {
+			session.run(&["XADD", "s", id, "f", value]);
+		}
+		session
+	}
+
+	#[test]
+	fn xrange_over_everything() {
+		let mut session = three_entries();
+		let all = entries(&[("1-1", "a"), ("2-1", "b"), ("3-1", "c")]);
+		assert_eq!(session.run(&["XRANGE", "s", "-", "+"]), all);
+		assert_eq!(session.run(&["XRANGE", "s", "1", "3"]), all);
+		assert_eq!(
+			session.run(&["XREVRANGE", "s", "+", "-"]),
+			entries(&[("3-1", "c"), ("2-1", "b"), ("1-1", "a")])
+		);
+		assert_eq!(session.run(&["XRANGE", "missing", "-", "+"]), b"*0\r\n");
+	}
+
+	#[test]
+	fn xrange_exclusive_bounds() {
+		let mut session = three_entries();
+		assert_eq!(
+			session.run(&["XRANGE", "s", "(1-1", "+"]),
+			entries(&[("2-1", "b"), ("3-1", "c")])
+		);
+		assert_eq!(session.run(&["XRANGE", "s", "(1-1", "(3-1"]), entries(&[("2-1", "b")]));
+		assert_eq!(session.run(&["XRANGE", "s", "(2-1", "(3-1"]), b"*0\r\n");
+	}
+
+	#[test]
+	fn xrange_count_limits_the_reply() {
+		let mut session = three_entries();
+		assert_eq!(
+			session.run(&["XRANGE", "s", "-", "+", "COUNT", "2"]),
+			entries(&[("1-1", "a"), ("2-1", "b")])
+		);
+		assert_eq!(
+			session.run(&["XREVRANGE", "s", "+", "-", "COUNT", "1"]),
+			entries(&[("3-1", "c")])
+		);
+		assert_eq!(session.run(&["XRANGE", "s", "-", "+", "COUNT", "0"]), b"*0\r\n");
+	}
+}
@@ -0,0 +1,1271 @@
+use super::db::{now_ms, Value};
+use super::object::canonical_int;
+use super::types::*;
+use bytes::{Bytes, BytesMut};
+use std::convert::TryFrom;
+use std::mem;
+use std::str;
+
+/// Resolve Redis-style inclusive `start`/`end` indices (negative values
+/// count from the end) into a byte range of a value of length `len`.
+///
+/// Out-of-range indices are clamped rather than rejected, and an empty
+/// range is returned as `None`.
+fn clamp_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+	let len = len as i64;
+	let mut start = if start < 0 { len + start } else { start };
+	let mut end = if end < 0 { len + end } else { end };
+	if start < 0 {
+		start = 0;
+	}
+	if end < 0 {
+		end = 0;
+	}
+	if end >= len {
+		end = len - 1;
+	}
+	if len == 0 || start > end {
+		return None;
+	}
+	Some((start as usize, end as usize + 1))
+}
+
+/// Check that growing a string to `offset + len` bytes stays within
+/// `proto-max-bulk-len`, before anything is allocated.
+fn check_string_length(state: &State, offset: usize, len: usize) -> Option<ReturnValue> {
+	match offset.checked_add(len) {
+		Some(size) if size <= state.proto_max_bulk_len => None,
+		_ => Some(ReturnValue::Error(Bytes::from(
+			"ERR string exceeds maximum allowed size (proto-max-bulk-len)",
+		))),
+	}
+}
+
+/// `GETRANGE key start end`
+pub fn getrange(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let (start, end) = match (integer_arg(&a[2]), integer_arg(&a[3])) {
+		(Some(start), Some(end)) => (start, end),
+		_ => return Ok(not_an_integer()),
+	};
+
+	let value = match state.db.lock().unwrap().get(&key)? {
+		Some(value) => value,
+		None => Bytes::new(),
+	};
+	let range = match clamp_range(start, end, value.len()) {
+		Some((from, to)) => value.slice(from..to),
+		None => Bytes::new(),
+	};
+	Ok(ReturnValue::StringRes(range))
+}
+
+/// `SET key value [GET] [EX seconds | PX milliseconds | KEEPTTL]`
+///
+/// Replies `OK`, or with `GET` the previous value (nil if there was none).
+pub fn set(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let value = Bytes::try_from(a[2].clone())?;
+
+	let (mut deadline, mut keep_ttl, mut get) = (None, false, false);
+	let mut i = 3;
+	while i < a.len() {
+		let opt = Bytes::try_from(a[i].clone())?;
+		match opt.to_ascii_lowercase().as_slice() {
+			unit @ b"ex" | unit @ b"px" if deadline.is_none() && !keep_ttl && i + 1 < a.len() => {
+				let scale = if unit == b"ex" { 1000 } else { 1 };
+				deadline = match integer_arg(&a[i + 1]) {
+					Some(n) if n > 0 => match n.checked_mul(scale) {
+						Some(ms) => Some(now_ms().saturating_add(state.jitter_ttl(ms as u64))),
+						None => return Ok(invalid_expire_time("set")),
+					},
+					Some(_) => return Ok(invalid_expire_time("set")),
+					None => return Ok(not_an_integer()),
+				};
+				i += 1;
+			}
+			b"keepttl" if deadline.is_none() => keep_ttl = true,
+			b"get" => get = true,
+			_ => return Ok(syntax_error()),
+		}
+		i += 1;
+	}
+
+	let mut db = state.db.lock().unwrap();
+	// Read first, so a key of another type is left untouched.
+	let old_value = if get { db.get(&key)? } else { None };
+	if keep_ttl {
+		db.update(key.clone(), value);
+	} else {
+		db.insert(key.clone(), value);
+	}
+	if let Some(deadline) = deadline {
+		db.set_expiry(&key, deadline);
+	}
+	match old_value {
+		Some(old_value) => Ok(ReturnValue::StringRes(old_value)),
+		None if get => Ok(ReturnValue::Nil),
+		None => Ok(ReturnValue::Ok),
+	}
+}
+
+/// `SETEX key seconds value`
+pub fn setex(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	set_with_expiry(a, state, "setex", 1000)
+}
+
+/// `PSETEX key milliseconds value`
+pub fn psetex(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	set_with_expiry(a, state, "psetex", 1)
+}
+
+/// Shared by `SETEX` and `PSETEX`, whose expiry is in units of `scale`
+/// milliseconds. The expiry is checked before anything is written.
+fn set_with_expiry(
+	a: &[RedisValue],
+	state: &State,
+	name: &str,
+	scale: i64,
+) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let ms = match integer_arg(&a[2]) {
+		Some(n) if n > 0 => match n.checked_mul(scale) {
+			Some(ms) => ms,
+			None => return Ok(invalid_expire_time(name)),
+		},
+		Some(_) => return Ok(invalid_expire_time(name)),
+		None => return Ok(not_an_integer()),
+	};
+	let value = Bytes::try_from(a[3].clone())?;
+
+	let mut db = state.db.lock().unwrap();
+	db.insert(key.clone(), value);
+	db.set_expiry(&key, now_ms().saturating_add(state.jitter_ttl(ms as u64)));
+	Ok(ReturnValue::Ok)
+}
+
+/// `GETDEL key`
+pub fn getdel(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let mut db = state.db.lock().unwrap();
+	match db.get(&key)? {
+		Some(value) => {
+			db.remove(&key);
+			Ok(ReturnValue::StringRes(value))
+		}
+		None => Ok(ReturnValue::Nil),
+	}
+}
+
+/// `MGET key [key ...]`. Keys that are missing or don't hold a string
+/// reply nil.
+pub fn mget(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let mut db = state.db.lock().unwrap();
+	let mut values = Vec::with_capacity(a.len() - 1);
+	for key in &a[1..] {
+		let key = Bytes::try_from(key.clone())?;
+		values.push(match db.get(&key) {
+			Ok(Some(value)) => ReturnValue::StringRes(value),
+			_ => ReturnValue::Nil,
+		});
+	}
+	Ok(ReturnValue::Array(values))
+}
+
+/// `MSET key value [key value ...]`
+pub fn mset(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	if a.len().is_multiple_of(2) {
+		return Ok(wrong_number_of_arguments("mset"));
+	}
+	let mut pairs = Vec::with_capacity(a.len() / 2);
+	for pair in a[1..].chunks(2) {
+		pairs.push((Bytes::try_from(pair[0].clone())?, Bytes::try_from(pair[1].clone())?));
+	}
+	let mut db = state.db.lock().unwrap();
+	for (key, value) in pairs {
+		db.insert(key, value);
+	}
+	Ok(ReturnValue::Ok)
+}
+
+/// `GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]`
+///
+/// Like `GET`, optionally changing the key's TTL. A deadline already in
+/// the past deletes the key, after its value is read.
+pub fn getex(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	// `Some(None)` is PERSIST.
+	let deadline = match a.len() {
+		2 => None,
+		3 => {
+			let opt = Bytes::try_from(a[2].clone())?;
+			if opt.to_ascii_lowercase() != b"persist" {
+				return Ok(syntax_error());
+			}
+			Some(None)
+		}
+		4 => {
+			let opt = Bytes::try_from(a[2].clone())?;
+			let (scale, relative) = match opt.to_ascii_lowercase().as_slice() {
+				b"ex" => (1000, true),
+				b"px" => (1, true),
+				b"exat" => (1000, false),
+				b"pxat" => (1, false),
+				_ => return Ok(syntax_error()),
+			};
+			let base = if relative { now_ms() as i64 } else { 0 };
+			match integer_arg(&a[3]) {
+				Some(n) if n > 0 => match n.checked_mul(scale).and_then(|ms| ms.checked_add(base)) {
+					Some(deadline) => Some(Some(deadline)),
+					None => return Ok(invalid_expire_time("getex")),
+				},
+				Some(_) => return Ok(invalid_expire_time("getex")),
+				None => return Ok(not_an_integer()),
+			}
+		}
+		_ => return Ok(syntax_error()),
+	};
+
+	let mut db = state.db.lock().unwrap();
+	let value = match db.get(&key)? {
+		Some(value) => value,
+		None => return Ok(ReturnValue::Nil),
+	};
+	match deadline {
+		None => {}
+		Some(None) => db.persist(&key),
+		Some(Some(deadline)) if deadline <= now_ms() as i64 => {
+			db.remove(&key);
+		}
+		Some(Some(deadline)) => {
+			db.set_expiry(&key, deadline as u64);
+		}
+	}
+	Ok(ReturnValue::StringRes(value))
+}
+
+/// `SETRANGE key offset value`
+pub fn setrange(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let offset = match integer_arg(&a[2]) {
+		Some(offset) if offset >= 0 => offset as usize,
+		Some(_) => return Ok(ReturnValue::Error(Bytes::from("ERR offset is out of range"))),
+		None => return Ok(not_an_integer()),
+	};
+	let patch = Bytes::try_from(a[3].clone())?;
+
+	let mut db = state.db.lock().unwrap();
+	let current = db.get(&key)?.unwrap_or_default();
+	// An empty patch never creates or grows the value.
+	if patch.is_empty() {
+		return Ok(ReturnValue::IntRes(current.len() as i64));
+	}
+	if let Some(err) = check_string_length(state, offset, patch.len()) {
+		return Ok(err);
+	}
+
+	let mut value = BytesMut::from(&current[..]);
+	let end = offset + patch.len();
+	if value.len() < end {
+		value.resize(end, 0);
+	}
+	value[offset..end].copy_from_slice(&patch);
+	let len = value.len();
+	db.update(key, value.freeze());
+	Ok(ReturnValue::IntRes(len as i64))
+}
+
+/// `APPEND key value`. A missing key is created, as if appending to an
+/// empty string.
+pub fn append(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let suffix = Bytes::try_from(a[2].clone())?;
+
+	let mut db = state.db.lock().unwrap();
+	let current = db.get(&key)?;
+	let current_len = current.as_ref().map_or(0, |current| current.len());
+	if let Some(err) = check_string_length(state, current_len, suffix.len()) {
+		return Ok(err);
+	}
+	let current = match current {
+		Some(current) => current,
+		None => {
+			let len = suffix.len();
+			db.insert(key, suffix);
+			return Ok(ReturnValue::IntRes(len as i64));
+		}
+	};
+	let mut value = BytesMut::with_capacity(current.len() + suffix.len());
+	value.extend_from_slice(&current);
+	value.extend_from_slice(&suffix);
+	let len = value.len();
+	db.update(key, value.freeze());
+	Ok(ReturnValue::IntRes(len as i64))
+}
+
+fn bit_at(value: &[u8], pos: usize) -> u8 {
+	(value[pos / 8] >> (7 - pos % 8)) & 1
+}
+
+/// `BITPOS key bit [start [end [BYTE | BIT]]]`
+pub fn bitpos(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	if a.len() > 6 {
+		return Ok(syntax_error());
+	}
+	let key = Bytes::try_from(a[1].clone())?;
+	let bit = match integer_arg(&a[2]) {
+		Some(bit @ 0..=1) => bit as u8,
+		_ => {
+			return Ok(ReturnValue::Error(Bytes::from(
+				"ERR The bit argument must be 1 or 0.",
+			)))
+		}
+	};
+
+	let value = match state.db.lock().unwrap().get(&key)? {
+		Some(value) => value,
+		// A missing key is an infinite run of zero bits.
+		None => return Ok(ReturnValue::IntRes(if bit == 1 { -1 } else { 0 })),
+	};
+
+	let bit_unit = match a.get(5) {
+		None => false,
+		Some(unit) => match Bytes::try_from(unit.clone())?.to_ascii_lowercase().as_slice() {
+			b"byte" => false,
+			b"bit" => true,
+			_ => return Ok(syntax_error()),
+		},
+	};
+	let total = if bit_unit { value.len() * 8 } else { value.len() } as i64;
+	let start = match a.get(3).map(integer_arg) {
+		None => 0,
+		Some(Some(start)) => start,
+		Some(None) => return Ok(not_an_integer()),
+	};
+	let end_given = a.len() > 4;
+	let end = match a.get(4).map(integer_arg) {
+		None => total - 1,
+		Some(Some(end)) => end,
+		Some(None) => return Ok(not_an_integer()),
+	};
+	let (first, last) = match clamp_range(start, end, total as usize) {
+		Some((from, to)) => (from, to - 1),
+		None => return Ok(ReturnValue::IntRes(-1)),
+	};
+	let (first_bit, last_bit) = if bit_unit {
+		(first, last)
+	} else {
+		(first * 8, last * 8 + 7)
+	};
+
+	let skip = if bit == 1 { 0x00 } else { 0xff };
+	let mut pos = first_bit;
+	while pos <= last_bit {
+		// Whole bytes that can't contain the bit are skipped at once.
+		if pos % 8 == 0 && pos + 7 <= last_bit && value[pos / 8] == skip {
+			pos += 8;
+			continue;
+		}
+		if bit_at(&value, pos) == bit {
+			return Ok(ReturnValue::IntRes(pos as i64));
+		}
+		pos += 1;
+	}
+
+	// Looking for a clear bit with an open-ended range: the string is
+	// treated as padded with zeros, so the answer is the first bit past it.
+	if bit == 0 && !end_given {
+		return Ok(ReturnValue::IntRes(last_bit as i64 + 1));
+	}
+	Ok(ReturnValue::IntRes(-1))
+}
+
+/// What `BITFIELD` does when a `SET` or `INCRBY` result doesn't fit.
+#[derive(Clone, Copy)]
+enum Overflow {
+	Wrap,
+	Sat,
+	Fail,
+}
+
+/// A `BITFIELD` field type such as `u8` or `i16`.
+#[derive(Clone, Copy)]
+struct FieldType {
+	signed: bool,
+	bits: u32,
+}
+
+impl FieldType {
+	/// `i1`..`i64` or `u1`..`u63`; `u64` can't be returned as a Redis
+	/// integer, so Redis doesn't allow it.
+	fn parse(arg: &[u8]) -> Option<FieldType> {
+		let signed = match arg.first().map(u8::to_ascii_lowercase) {
+			Some(b'i') => true,
+			Some(b'u') => false,
+			_ => return None,
+		};
+		let bits: u32 = str::from_utf8(&arg[1..]).ok()?.parse().ok()?;
+		let max = if signed { 64 } else { 63 };
+		if bits == 0 || bits > max {
+			return None;
+		}
+		Some(FieldType { signed, bits })
+	}
+
+	fn min(self) -> i128 {
+		if self.signed {
+			-(1i128 << (self.bits - 1))
+		} else {
+			0
+		}
+	}
+
+	fn max(self) -> i128 {
+		if self.signed {
+			(1i128 << (self.bits - 1)) - 1
+		} else {
+			(1i128 << self.bits) - 1
+		}
+	}
+
+	/// Fit `n` into the field, or `None` if `overflow` is `FAIL` and it
+	/// doesn't.
+	fn fit(self, n: i128, overflow: Overflow) -> Option<i128> {
+		if n >= self.min() && n <= self.max() {
+			return Some(n);
+		}
+		match overflow {
+			Overflow::Fail => None,
+			Overflow::Sat => Some(n.clamp(self.min(), self.max())),
+			Overflow::Wrap => {
+				let modulus = 1i128 << self.bits;
+				let wrapped = n.rem_euclid(modulus);
+				Some(if self.signed && wrapped > self.max() {
+					wrapped - modulus
+				} else {
+					wrapped
+				})
+			}
+		}
+	}
+}
+
+enum FieldOp {
+	Get,
+	Set(i64),
+	IncrBy(i64),
+}
+
+struct BitfieldOp {
+	op: FieldOp,
+	field: FieldType,
+	offset: u64,
+	overflow: Overflow,
+}
+
+/// Read `bits` bits starting at bit `offset`, most significant first.
+/// Bits past the end of `value` read as zero.
+fn read_field(value: &[u8], offset: u64, field: FieldType) -> i128 {
+	let mut n: u64 = 0;
+	for i in 0..field.bits as u64 {
+		let pos = offset + i;
+		let byte = (pos / 8) as usize;
+		let bit = if byte < value.len() {
+			(value[byte] >> (7 - pos % 8)) & 1
+		} else {
+			0
+		};
+		n = (n << 1) | bit as u64;
+	}
+	let n = n as i128;
+	if field.signed && n > field.max() {
+		n - (1i128 << field.bits)
+	} else {
+		n
+	}
+}
+
+/// Write the low `bits` bits of `n` at bit `offset`. `value` must already
+/// be long enough.
+fn write_field(value: &mut [u8], offset: u64, field: FieldType, n: i128) {
+	for i in 0..field.bits as u64 {
+		let pos = offset + i;
+		let mask = 1u8 << (7 - pos % 8);
+		let byte = &mut value[(pos / 8) as usize];
+		if (n >> (field.bits as u64 - 1 - i)) & 1 == 1 {
+			*byte |= mask;
+		} else {
+			*byte &= !mask;
+		}
+	}
+}
+
+/// `BITFIELD key [GET type offset] [SET type offset value]
+/// [INCRBY type offset increment] [OVERFLOW WRAP | SAT | FAIL] ...`
+pub fn bitfield(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	bitfield_command(a, state, false)
+}
+
+/// `BITFIELD_RO key [GET type offset ...]`
+pub fn bitfield_ro(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	bitfield_command(a, state, true)
+}
+
+fn bitfield_command(
+	a: &[RedisValue],
+	state: &State,
+	read_only: bool,
+) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+
+	// Every operation is parsed before any is run, so a bad argument
+	// anywhere leaves the value untouched.
+	let mut ops = Vec::new();
+	let mut overflow = Overflow::Wrap;
+	let mut i = 2;
+	while i < a.len() {
+		let name = Bytes::try_from(a[i].clone())?.to_ascii_lowercase();
+		if read_only && name != b"get" {
+			return Ok(ReturnValue::Error(Bytes::from(
+				"ERR BITFIELD_RO only supports the GET subcommand",
+			)));
+		}
+		if name == b"overflow" {
+			let mode = match a.get(i + 1) {
+				Some(mode) => Bytes::try_from(mode.clone())?,
+				None => return Ok(syntax_error()),
+			};
+			overflow = match mode.to_ascii_lowercase().as_slice() {
+				b"wrap" => Overflow::Wrap,
+				b"sat" => Overflow::Sat,
+				b"fail" => Overflow::Fail,
+				_ => {
+					return Ok(ReturnValue::Error(Bytes::from(
+						"ERR Invalid OVERFLOW type specified",
+					)))
+				}
+			};
+			i += 2;
+			continue;
+		}
+		let argc = match name.as_slice() {
+			b"get" => 2,
+			b"set" | b"incrby" => 3,
+			_ => return Ok(syntax_error()),
+		};
+		if i + argc >= a.len() {
+			return Ok(syntax_error());
+		}
+		let field = match FieldType::parse(&Bytes::try_from(a[i + 1].clone())?) {
+			Some(field) => field,
+			None => {
+				return Ok(ReturnValue::Error(Bytes::from(
+					"ERR Invalid bitfield type. Use something like i16 u8. \
+					 Note that u64 is not supported but i64 is.",
+				)))
+			}
+		};
+		let offset = match field_offset(&Bytes::try_from(a[i + 2].clone())?, field, state) {
+			Some(offset) => offset,
+			None => {
+				return Ok(ReturnValue::Error(Bytes::from(
+					"ERR bit offset is not an integer or out of range",
+				)))
+			}
+		};
+		let op = match name.as_slice() {
+			b"get" => FieldOp::Get,
+			_ => match integer_arg(&a[i + 3]) {
+				Some(n) if name == b"set" => FieldOp::Set(n),
+				Some(n) => FieldOp::IncrBy(n),
+				None => return Ok(not_an_integer()),
+			},
+		};
+		ops.push(BitfieldOp {
+			op,
+			field,
+			offset,
+			overflow,
+		});
+		i += argc + 1;
+	}
+
+	let mut db = state.db.lock().unwrap();
+	let current = db.get(&key)?;
+	// Writes create the key, zero-padded out to the furthest field
+	// written, even if every write then fails on overflow.
+	let write_end = ops
+		.iter()
+		.filter(|op| !matches!(op.op, FieldOp::Get))
+		.map(|op| (op.offset + op.field.bits as u64).div_ceil(8) as usize)
+		.max();
+	let mut value = BytesMut::from(&current.clone().unwrap_or_else(Bytes::new)[..]);
+	if let Some(end) = write_end {
+		if value.len() < end {
+			value.resize(end, 0);
+		}
+	}
+
+	let mut results = Vec::with_capacity(ops.len());
+	for op in &ops {
+		let old = read_field(&value, op.offset, op.field);
+		let new = match op.op {
+			FieldOp::Get => {
+				results.push(ReturnValue::IntRes(old as i64));
+				continue;
+			}
+			FieldOp::Set(n) => op.field.fit(n as i128, op.overflow),
+			FieldOp::IncrBy(n) => op.field.fit(old + n as i128, op.overflow),
+		};
+		match new {
+			Some(new) => {
+				write_field(&mut value, op.offset, op.field, new);
+				results.push(ReturnValue::IntRes(match op.op {
+					FieldOp::Set(_) => old,
+					_ => new,
+				} as i64));
+			}
+			None => results.push(ReturnValue::Nil),
+		}
+	}
+	if write_end.is_some() {
+		db.update(key, value.freeze());
+	}
+	Ok(ReturnValue::Array(results))
+}
+
+/// Parse a `BITFIELD` offset: a bit offset, or `#n` for the n-th field of
+/// this type. The field must end within `proto-max-bulk-len`.
+fn field_offset(arg: &[u8], field: FieldType, state: &State) -> Option<u64> {
+	let (multiplier, digits) = match arg.first() {
+		Some(b'#') => (field.bits as u64, &arg[1..]),
+		_ => (1, arg),
+	};
+	let n: u64 = str::from_utf8(digits).ok()?.parse().ok()?;
+	let offset = n.checked_mul(multiplier)?;
+	let end = offset.checked_add(field.bits as u64)?;
+	if check_string_length(state, end.div_ceil(8) as usize, 0).is_some() {
+		return None;
+	}
+	Some(offset)
+}
+
+/// `LCS key1 key2 [LEN] [IDX] [MINMATCHLEN len] [WITHMATCHLEN]`
+pub fn lcs(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	let (mut get_len, mut get_idx, mut with_match_len) = (false, false, false);
+	let mut min_match_len = 0;
+	let mut i = 3;
+	while i < a.len() {
+		let opt = Bytes::try_from(a[i].clone())?;
+		match opt.to_ascii_lowercase().as_slice() {
+			b"idx" => get_idx = true,
+			b"len" => get_len = true,
+			b"withmatchlen" => with_match_len = true,
+			b"minmatchlen" if i + 1 < a.len() => {
+				min_match_len = match integer_arg(&a[i + 1]) {
+					Some(n) => n.max(0) as usize,
+					None => return Ok(not_an_integer()),
+				};
+				i += 1;
+			}
+			_ => return Ok(syntax_error()),
+		}
+		i += 1;
+	}
+	if get_len && get_idx {
+		return Ok(ReturnValue::Error(Bytes::from(
+			"ERR If you want both the length and indexes, please just use IDX.",
+		)));
+	}
+
+	// Missing keys compare as empty strings.
+	let (va, vb) = {
+		let mut db = state.db.lock().unwrap();
+		let mut lookup = |key: &RedisValue| -> Result<Bytes, ReturnError> {
+			let key = Bytes::try_from(key.clone())?;
+			Ok(db.get(&key)?.unwrap_or_default())
+		};
+		(lookup(&a[1])?, lookup(&a[2])?)
+	};
+	let (alen, blen) = (va.len(), vb.len());
+
+	// dp[i][j] is the LCS length of the first i bytes of A and the first
+	// j bytes of B, stored row-major. As in Redis, the table may be no
+	// larger than `proto-max-bulk-len`, checked before it is allocated.
+	let cells = (alen + 1).checked_mul(blen + 1);
+	let table_size = cells.and_then(|cells| cells.checked_mul(mem::size_of::<u32>()));
+	let cells = match (cells, table_size) {
+		(Some(cells), Some(size)) if size <= state.proto_max_bulk_len => cells,
+		_ => {
+			return Ok(ReturnValue::Error(Bytes::from(
+				"ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len",
+			)))
+		}
+	};
+	let mut dp = vec![0u32; cells];
+	let at = |i: usize, j: usize| i * (blen + 1) + j;
+	for i in 1..=alen {
+		for j in 1..=blen {
+			dp[at(i, j)] = if va[i - 1] == vb[j - 1] {
+				dp[at(i - 1, j - 1)] + 1
+			} else {
+				dp[at(i - 1, j)].max(dp[at(i, j - 1)])
+			};
+		}
+	}
+	let len = dp[at(alen, blen)] as usize;
+	if get_len {
+		return Ok(ReturnValue::IntRes(len as i64));
+	}
+
+	// Walk back from the end, rebuilding the subsequence and collecting
+	// the contiguous matching ranges, last match first.
+	let mut result = vec![0u8; len];
+	let mut matches = Vec::new();
+	let mut idx = len;
+	let (mut i, mut j) = (alen, blen);
+	let mut range: Option<(usize, usize, usize, usize)> = None;
+	while i > 0 && j > 0 {
+		let mut emit = false;
+		if va[i - 1] == vb[j - 1] {
+			result[idx - 1] = va[i - 1];
+			range = match range {
+				None => Some((i - 1, i - 1, j - 1, j - 1)),
+				Some((a_start, a_end, b_start, b_end)) => {
+					Some((a_start - 1, a_end, b_start - 1, b_end))
+				}
+			};
+			if let Some((a_start, _, b_start, _)) = range {
+				// A match at the start of either string ends the walk.
+				emit = a_start == 0 || b_start == 0;
+			}
+			idx -= 1;
+			i -= 1;
+			j -= 1;
+		} else {
+			if dp[at(i - 1, j)] > dp[at(i, j - 1)] {
+				i -= 1;
+			} else {
+				j -= 1;
+			}
+			emit = range.is_some();
+		}
+
+		if emit {
+			if let Some((a_start, a_end, b_start, b_end)) = range.take() {
+				let match_len = a_end - a_start + 1;
+				if get_idx && (min_match_len == 0 || match_len >= min_match_len) {
+					let mut item = vec![
+						ReturnValue::Array(vec![
+							ReturnValue::IntRes(a_start as i64),
+							ReturnValue::IntRes(a_end as i64),
+						]),
+						ReturnValue::Array(vec![
+							ReturnValue::IntRes(b_start as i64),
+							ReturnValue::IntRes(b_end as i64),
+						]),
+					];
+					if with_match_len {
+						item.push(ReturnValue::IntRes(match_len as i64));
+					}
+					matches.push(ReturnValue::Array(item));
+				}
+			}
+		}
+	}
+
+	if get_idx {
+		return Ok(ReturnValue::Array(vec![
+			ReturnValue::StringRes(Bytes::from("matches")),
+			ReturnValue::Array(matches),
+			ReturnValue::StringRes(Bytes::from("len")),
+			ReturnValue::IntRes(len as i64),
+		]));
+	}
+	Ok(ReturnValue::StringRes(Bytes::from(result)))
+}
+
+/// `INCR key`
+pub fn incr(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	incr_by(a, state, 1)
+}
+
+/// `DECR key`
+pub fn decr(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	incr_by(a, state, -1)
+}
+
+/// `INCRBY key increment`
+pub fn incrby(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	match integer_arg(&a[2]) {
+		Some(delta) => incr_by(a, state, delta),
+		None => Ok(not_an_integer()),
+	}
+}
+
+/// `DECRBY key decrement`
+pub fn decrby(a: &[RedisValue], state: &mut State) -> Result<ReturnValue, ReturnError> {
+	match integer_arg(&a[2]).map(i64::checked_neg) {
+		Some(Some(delta)) => incr_by(a, state, delta),
+		Some(None) => Ok(ReturnValue::Error(Bytes::from("ERR decrement would overflow"))),
+		None => Ok(not_an_integer()),
+	}
+}
+
+/// Add `delta` to the integer at `a[1]`, a missing key counting as 0.
+///
+/// The result is stored as a number, so a counter is only parsed the
+/// first time it's incremented and only formatted when it's read. Any TTL
+/// is kept.
+fn incr_by(a: &[RedisValue], state: &State, delta: i64) -> Result<ReturnValue, ReturnError> {
+	let key = Bytes::try_from(a[1].clone())?;
+	let mut db = state.db.lock().unwrap();
+	let value = match db.get_value_mut(&key) {
+		Some(value) => value,
+		None => {
+			db.insert(key, Value::Int(delta));
+			return Ok(ReturnValue::IntRes(delta));
+		}
+	};
+	let current = match value {
+		Value::Int(n) => *n,
+		// Only the canonical form converts, so GET gives back the same bytes.
+		Value::String(s) => match canonical_int(s) {
+			Some(n) => n,
+			None => return Ok(not_an_integer()),
+		},
+		_ => return Err(ReturnError::WrongType),
+	};
+	match current.checked_add(delta) {
+		Some(n) => {
+			*value = Value::Int(n);
+			Ok(ReturnValue::IntRes(n))
+		}
+		None => Ok(ReturnValue::Error(Bytes::from(
+			"ERR increment or decrement would overflow",
+		))),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Session;
+	use crate::types::ServerState;
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn getex_without_options_is_get() {
+		let mut session = Session::new();
+		session.run(&["SET", "k", "v", "EX", "100"]);
+		assert_eq!(session.run(&["GETEX", "k"]), b"$1\r\nv\r\n");
+		assert_eq!(session.run(&["TTL", "k"]), b":100\r\n");
+		assert_eq!(session.run(&["GETEX", "missing"]), b"$-1\r\n");
+	}
+
+	#[test]
+	fn getex_ex_sets_a_ttl() {
+		let mut session = Session::new();
+		session.run(&["SET", "k", "v"]);
+		assert_eq!(session.run(&["TTL", "k"]), b":-1\r\n");
+		assert_eq!(session.run(&["GETEX", "k", "EX", "50"]), b"$1\r\nv\r\n");
+		assert_eq!(session.run(&["TTL", "k"]), b":50\r\n");
+		assert_eq!(session.run(&["GETEX", "k", "PX", "20000"]), b"$1\r\nv\r\n");
+		assert_eq!(session.run(&["TTL", "k"]), b":20\r\n");
+	}
+
+	#[test]
+	fn getex_exat_and_pxat_set_the_deadline() {
+		let mut session = Session::new();
+		session.run(&["SET", "k", "v"]);
+		session.run(&["GETEX", "k", "EXAT", "33177600000"]);
+		assert_eq!(session.run(&["EXPIRETIME", "k"]), b":33177600000\r\n");
+		session.run(&["GETEX", "k", "PXAT", "33177600000123"]);
+		assert_eq!(session.run(&["PEXPIRETIME", "k"]), b":33177600000123\r\n");
+	}
+
+	#[test]
+	fn getex_persist_clears_the_ttl() {
+		let mut session = Session::new();
+		session.run(&["SET", "k", "v", "EX", "100"]);
+		assert_eq!(session.run(&["GETEX", "k", "PERSIST"]), b"$1\r\nv\r\n");
+		assert_eq!(session.run(&["TTL", "k"]), b":-1\r\n");
+	}
+
+	#[test]
+	fn getex_past_deadline_deletes_after_reading() {
+		let mut session = Session::new();
+		session.run(&["SET", "k", "v"]);
+		assert_eq!(session.run(&["GETEX", "k", "EXAT", "1"]), b"$1\r\nv\r\n");
+		assert_eq!(session.run(&["EXISTS", "k"]), b":0\r\n");
+	}
+
+	#[test]
+	fn getex_rejects_bad_options() {
+		let mut session = Session::new();
+		session.run(&["SET", "k", "v"]);
+		assert_eq!(
+			session.run(&["GETEX", "k", "EX", "0"]),
+			&b"-ERR invalid expire time in 'getex' command\r\n"[..]
+		);
+		assert_eq!(session.run(&["GETEX", "k", "EX"]), b"-ERR syntax error\r\n");
+		assert_eq!(session.run(&["GETEX", "k", "EX", "1", "PERSIST"]), b"-ERR syntax error\r\n");
+		assert_eq!(
+			session.run(&["GETEX", "k", "EX", "x"]),
+			&b"-ERR value is not an integer or out of range\r\n"[..]
+		);
+		assert_eq!(session.run(&["TTL", "k"]), b":-1\r\n");
+	}
+
+	#[test]
+	fn getrange_slices_binary_values_by_byte() {
+		let mut session = Session::new();
+		let value: &[u8] = b"\xff\x00\xc3(\x80abc";
+		session.run_bytes(&[b"SET", b"bin", value]);
+		assert_eq!(session.run(&["GETRANGE", "bin", "0", "2"]), b"$3\r\n\xff\x00\xc3\r\n");
+		assert_eq!(session.run(&["GETRANGE", "bin", "3", "4"]), b"$2\r\n(\x80\r\n");
+		assert_eq!(session.run(&["GETRANGE", "bin", "-3", "-1"]), b"$3\r\nabc\r\n");
+		// Out-of-range indices are clamped rather than panicking.
+		assert_eq!(
+			session.run(&["GETRANGE", "bin", "-100", "100"]),
+			&b"$8\r\n\xff\x00\xc3(\x80abc\r\n"[..]
+		);
+		assert_eq!(session.run(&["GETRANGE", "bin", "5", "2"]), b"$0\r\n\r\n");
+		assert_eq!(session.run(&["GETRANGE", "bin", "100", "200"]), b"$0\r\n\r\n");
+	}
+
+	#[test]
+	fn setrange_patches_binary_values_by_byte() {
+		let mut session = Session::new();
+		session.run_bytes(&[b"SET", b"bin", b"\xff\xfe\xfd\xfc"]);
+		assert_eq!(session.run_bytes(&[b"SETRANGE", b"bin", b"1", b"\x00\x80"]), b":4\r\n");
+		assert_eq!(session.run(&["GET", "bin"]), b"$4\r\n\xff\x00\x80\xfc\r\n");
+		// Writing past the end zero-pads the gap.
+		assert_eq!(session.run_bytes(&[b"SETRANGE", b"bin", b"6", b"\xc3"]), b":7\r\n");
+		assert_eq!(session.run(&["GET", "bin"]), b"$7\r\n\xff\x00\x80\xfc\x00\x00\xc3\r\n");
+	}
+
+	#[test]
+	fn mset_then_mget() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["MSET", "a", "1", "b", "2"]), b"+OK\r\n");
+		session.run(&["XADD", "s", "*", "f", "v"]);
+		assert_eq!(
+			session.run(&["MGET", "a", "missing", "s", "b"]),
+			&b"*4\r\n$1\r\n1\r\n$-1\r\n$-1\r\n$1\r\n2\r\n"[..]
+		);
+		assert_eq!(
+			session.run(&["MSET", "a", "1", "b"]),
+			&b"-ERR wrong number of arguments for 'mset' command\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn bitpos_finds_the_first_set_bit() {
+		let mut session = Session::new();
+		session.run_bytes(&[b"SET", b"k", b"\x00\x0f\x80"]);
+		assert_eq!(session.run(&["BITPOS", "k", "1"]), b":12\r\n");
+		assert_eq!(session.run(&["BITPOS", "k", "1", "2"]), b":16\r\n");
+		assert_eq!(session.run(&["BITPOS", "k", "1", "13", "20", "BIT"]), b":13\r\n");
+		assert_eq!(session.run(&["BITPOS", "k", "0"]), b":0\r\n");
+	}
+
+	#[test]
+	fn bitpos_zero_in_all_ones_runs_past_the_end() {
+		let mut session = Session::new();
+		session.run_bytes(&[b"SET", b"ones", b"\xff\xff"]);
+		assert_eq!(session.run(&["BITPOS", "ones", "0"]), b":16\r\n");
+		assert_eq!(session.run(&["BITPOS", "ones", "0", "1"]), b":16\r\n");
+		// With an explicit end the search stops there.
+		assert_eq!(session.run(&["BITPOS", "ones", "0", "0", "-1"]), b":-1\r\n");
+		assert_eq!(session.run(&["BITPOS", "missing", "0"]), b":0\r\n");
+		assert_eq!(session.run(&["BITPOS", "missing", "1"]), b":-1\r\n");
+	}
+
+	#[test]
+	fn lcs_plain_len_and_idx() {
+		let mut session = Session::new();
+		session.run(&["MSET", "key1", "ohmytext", "key2", "mynewtext"]);
+		assert_eq!(session.run(&["LCS", "key1", "key2"]), b"$6\r\nmytext\r\n");
+		assert_eq!(session.run(&["LCS", "key1", "key2", "LEN"]), b":6\r\n");
+		assert_eq!(
+			session.run(&["LCS", "key1", "key2", "IDX"]),
+			&b"*4\r\n$7\r\nmatches\r\n*2\r\n\
+			*2\r\n*2\r\n:4\r\n:7\r\n*2\r\n:5\r\n:8\r\n\
+			*2\r\n*2\r\n:2\r\n:3\r\n*2\r\n:0\r\n:1\r\n\
+			$3\r\nlen\r\n:6\r\n"[..]
+		);
+		assert_eq!(
+			session.run(&["LCS", "key1", "key2", "IDX", "MINMATCHLEN", "4", "WITHMATCHLEN"]),
+			&b"*4\r\n$7\r\nmatches\r\n*1\r\n\
+			*3\r\n*2\r\n:4\r\n:7\r\n*2\r\n:5\r\n:8\r\n:4\r\n\
+			$3\r\nlen\r\n:6\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn lcs_of_missing_keys_is_empty() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["LCS", "nokey1", "nokey2"]), b"$0\r\n\r\n");
+		assert_eq!(session.run(&["LCS", "nokey1", "nokey2", "LEN"]), b":0\r\n");
+	}
+
+	fn limited(max_bulk_len: usize) -> Session {
+		let mut state = ServerState::new();
+		state.proto_max_bulk_len = max_bulk_len;
+		Session::with_state(state)
+	}
+
+	#[test]
+	fn growing_past_proto_max_bulk_len_is_refused() {
+		let mut session = limited(16);
+		let too_big = &b"-ERR string exceeds maximum allowed size (proto-max-bulk-len)\r\n"[..];
+		assert_eq!(session.run(&["SETRANGE", "k", "10", "0123456789"]), too_big);
+		assert_eq!(session.run(&["SETRANGE", "k", "6", "0123456789"]), b":16\r\n");
+		assert_eq!(session.run(&["APPEND", "k", "x"]), too_big);
+		// The parser already refuses longer arguments, so this is the most
+		// a missing key can be created with.
+		assert_eq!(session.run(&["APPEND", "new", "0123456789abcdef"]), b":16\r\n");
+	}
+
+	#[test]
+	fn getdel_and_getex_refuse_non_string_keys() {
+		let mut session = Session::new();
+		session.run(&["XADD", "s", "1-1", "f", "v"]);
+		let wrong_type =
+			&b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"[..];
+		assert_eq!(session.run(&["GETDEL", "s"]), wrong_type);
+		assert_eq!(session.run(&["GETEX", "s"]), wrong_type);
+		assert_eq!(session.run(&["GETEX", "s", "PERSIST"]), wrong_type);
+		assert_eq!(session.run(&["TYPE", "s"]), b"+stream\r\n");
+	}
+
+	#[test]
+	fn getdel_and_getex_return_binary_values_exactly() {
+		let mut session = Session::new();
+		let value: &[u8] = b"\x00\xff\r\n\x80";
+		session.run_bytes(&[b"SET", b"bin", value]);
+		assert_eq!(session.run(&["GETEX", "bin", "EX", "100"]), b"$5\r\n\x00\xff\r\n\x80\r\n");
+		assert_eq!(session.run(&["GETDEL", "bin"]), b"$5\r\n\x00\xff\r\n\x80\r\n");
+		assert_eq!(session.run(&["GETDEL", "bin"]), b"$-1\r\n");
+	}
+
+	#[test]
+	fn set_replies_ok_over_an_existing_key() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["SET", "k", "v1"]), b"+OK\r\n");
+		assert_eq!(session.run(&["SET", "k", "v2"]), b"+OK\r\n");
+		assert_eq!(session.run(&["GET", "k"]), b"$2\r\nv2\r\n");
+	}
+
+	#[test]
+	fn set_get_replies_with_the_previous_value() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["SET", "k", "v1", "GET"]), b"$-1\r\n");
+		assert_eq!(session.run(&["SET", "k", "v2", "GET"]), b"$2\r\nv1\r\n");
+		assert_eq!(session.run(&["GET", "k"]), b"$2\r\nv2\r\n");
+		session.run(&["XADD", "s", "1-1", "f", "v"]);
+		assert_eq!(
+			session.run(&["SET", "s", "v", "GET"]),
+			&b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"[..]
+		);
+		assert_eq!(session.run(&["TYPE", "s"]), b"+stream\r\n");
+	}
+
+	#[test]
+	fn set_ex_and_px_set_a_ttl() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["SET", "k", "v", "EX", "100"]), b"+OK\r\n");
+		assert_eq!(session.run(&["TTL", "k"]), b":100\r\n");
+		assert_eq!(session.run(&["SET", "k", "v", "PX", "100000"]), b"+OK\r\n");
+		assert_eq!(session.run(&["TTL", "k"]), b":100\r\n");
+		assert_eq!(session.run(&["SET", "k", "v"]), b"+OK\r\n");
+		assert_eq!(session.run(&["TTL", "k"]), b":-1\r\n");
+		assert_eq!(
+			session.run(&["SET", "k", "v", "EX", "0"]),
+			&b"-ERR invalid expire time in 'set' command\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn keys_are_gone_once_their_ttl_passes() {
+		let mut session = Session::new();
+		session.run(&["SET", "keep", "v"]);
+		assert_eq!(session.run(&["SET", "k", "v", "PX", "20"]), b"+OK\r\n");
+		assert_eq!(session.run(&["DBSIZE"]), b":2\r\n");
+		thread::sleep(Duration::from_millis(25));
+		assert_eq!(session.run(&["GET", "k"]), b"$-1\r\n");
+		assert_eq!(session.run(&["EXISTS", "k"]), b":0\r\n");
+		assert_eq!(session.run(&["TYPE", "k"]), b"+none\r\n");
+		assert_eq!(session.run(&["DBSIZE"]), b":1\r\n");
+	}
+
+	#[test]
+	fn psetex_sets_a_sub_second_ttl() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["PSETEX", "k", "1500", "v"]), b"+OK\r\n");
+		assert_eq!(session.run(&["GET", "k"]), b"$1\r\nv\r\n");
+		let pttl = session.run(&["PTTL", "k"]);
+		let ms: i64 = String::from_utf8_lossy(&pttl[1..pttl.len() - 2]).parse().unwrap();
+		assert!(ms > 1000 && ms <= 1500, "{}", ms);
+		assert_eq!(session.run(&["PSETEX", "k", "20", "v"]), b"+OK\r\n");
+		thread::sleep(Duration::from_millis(25));
+		assert_eq!(session.run(&["GET", "k"]), b"$-1\r\n");
+	}
+
+	#[test]
+	fn psetex_and_setex_refuse_non_positive_expiry() {
+		let mut session = Session::new();
+		for ms in &["0", "-5"] {
+			assert_eq!(
+				session.run(&["PSETEX", "k", ms, "v"]),
+				&b"-ERR invalid expire time in 'psetex' command\r\n"[..]
+			);
+			assert_eq!(
+				session.run(&["SETEX", "k", ms, "v"]),
+				&b"-ERR invalid expire time in 'setex' command\r\n"[..]
+			);
+		}
+		assert_eq!(session.run(&["EXISTS", "k"]), b":0\r\n");
+		assert_eq!(
+			session.run(&["PSETEX", "k", "soon", "v"]),
+			&b"-ERR value is not an integer or out of range\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn append_creates_then_extends_strings() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["APPEND", "k", "Hello"]), b":5\r\n");
+		assert_eq!(session.run(&["APPEND", "k", " World"]), b":11\r\n");
+		assert_eq!(session.run(&["GET", "k"]), b"$11\r\nHello World\r\n");
+		session.run(&["DEL", "k"]);
+		session.run(&["XADD", "k", "1-1", "f", "v"]);
+		assert_eq!(
+			session.run(&["APPEND", "k", "x"]),
+			&b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"[..]
+		);
+	}
+
+	#[test]
+	fn bitfield_u8_set_and_get() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["BITFIELD", "bf", "SET", "u8", "0", "200"]), b"*1\r\n:0\r\n");
+		assert_eq!(session.run(&["BITFIELD", "bf", "GET", "u8", "0"]), b"*1\r\n:200\r\n");
+		assert_eq!(session.run(&["BITFIELD", "bf", "SET", "u8", "#1", "7"]), b"*1\r\n:0\r\n");
+		assert_eq!(session.run(&["GET", "bf"]), b"$2\r\n\xc8\x07\r\n");
+		assert_eq!(session.run(&["BITFIELD_RO", "bf", "GET", "u8", "8"]), b"*1\r\n:7\r\n");
+	}
+
+	#[test]
+	fn bitfield_incrby_saturates() {
+		let mut session = Session::new();
+		session.run(&["BITFIELD", "bf", "SET", "u8", "0", "250"]);
+		assert_eq!(
+			session.run(&["BITFIELD", "bf", "OVERFLOW", "SAT", "INCRBY", "u8", "0", "10"]),
+			b"*1\r\n:255\r\n"
+		);
+		assert_eq!(
+			session.run(&["BITFIELD", "bf", "OVERFLOW", "SAT", "INCRBY", "u8", "0", "-300"]),
+			b"*1\r\n:0\r\n"
+		);
+		assert_eq!(
+			session.run(&["BITFIELD", "bf", "OVERFLOW", "WRAP", "INCRBY", "u8", "0", "-1"]),
+			b"*1\r\n:255\r\n"
+		);
+		assert_eq!(
+			session.run(&["BITFIELD", "bf", "OVERFLOW", "FAIL", "INCRBY", "u8", "0", "1"]),
+			b"*1\r\n$-1\r\n"
+		);
+	}
+
+	#[test]
+	fn incr_then_get_returns_text() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["INCR", "n"]), b":1\r\n");
+		assert_eq!(session.run(&["INCRBY", "n", "-11"]), b":-10\r\n");
+		assert_eq!(session.run(&["GET", "n"]), b"$3\r\n-10\r\n");
+		assert_eq!(session.run(&["DECR", "n"]), b":-11\r\n");
+		assert_eq!(session.run(&["APPEND", "n", "5"]), b":4\r\n");
+		assert_eq!(session.run(&["GET", "n"]), b"$4\r\n-115\r\n");
+		assert_eq!(session.run(&["INCR", "n"]), b":-114\r\n");
+	}
+
+	#[test]
+	fn getex_with_a_past_deadline_deletes_after_reading() {
+		let mut session = Session::new();
+		session.run(&["MSET", "a", "1", "b", "2"]);
+		assert_eq!(session.run(&["GETEX", "a", "EXAT", "1"]), b"$1\r\n1\r\n");
+		assert_eq!(session.run(&["EXISTS", "a"]), b":0\r\n");
+		assert_eq!(session.run(&["GETEX", "b", "PXAT", "1000"]), b"$1\r\n2\r\n");
+		assert_eq!(session.run(&["GET", "b"]), b"$-1\r\n");
+		assert_eq!(session.run(&["GETEX", "missing", "EXAT", "1"]), b"$-1\r\n");
+	}
+
+	#[test]
+	fn getrange_of_a_missing_key_is_empty() {
+		let mut session = Session::new();
+		assert_eq!(session.run(&["GETRANGE", "missing", "0", "-1"]), b"$0\r\n\r\n");
+		assert_eq!(session.run(&["GETRANGE", "missing", "5", "10"]), b"$0\r\n\r\n");
+		assert_eq!(session.run(&["EXISTS", "missing"]), b":0\r\n");
+	}
+
+	#[test]
+	fn lcs_refuses_tables_larger_than_proto_max_bulk_len() {
+		let mut session = limited(64);
+		session.run(&["MSET", "a", "ab", "b", "ab", "c", "abcdefgh", "d", "abcdefgh"]);
+		// 3 * 3 cells of 4 bytes fit in 64 bytes; 9 * 9 do not.
+		assert_eq!(session.run(&["LCS", "a", "b"]), b"$2\r\nab\r\n");
+		let too_big = "-ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len\r\n";
+		assert_eq!(session.run(&["LCS", "c", "d"]), too_big.as_bytes());
+	}
+
+	#[test]
+	fn set_keepttl_keeps_the_countdown() {
+		let mut session = Session::new();
+		session.run(&["SET", "k", "v1", "PX", "100000"]);
+		assert_eq!(session.run(&["SET", "k", "v2", "KEEPTTL"]), b"+OK\r\n");
+		assert_eq!(session.run(&["GET", "k"]), b"$2\r\nv2\r\n");
+		assert_eq!(session.run(&["TTL", "k"]), b":100\r\n");
+
+		session.run(&["SET", "short", "v1", "PX", "20"]);
+		session.run(&["SET", "short", "v2", "KEEPTTL"]);
+		thread::sleep(Duration::from_millis(25));
+		assert_eq!(session.run(&["GET", "short"]), b"$-1\r\n");
+
+		// Without KEEPTTL, SET clears the TTL.
+		assert_eq!(session.run(&["SET", "k", "v3"]), b"+OK\r\n");
+		assert_eq!(session.run(&["TTL", "k"]), b":-1\r\n");
+	}
+
+	#[test]
+	fn set_keepttl_conflicts_with_ex_and_px() {
+		let mut session = Session::new();
+		for args in &[
+			["SET", "k", "v", "KEEPTTL", "EX", "10"],
+			["SET", "k", "v", "EX", "10", "KEEPTTL"],
+			["SET", "k", "v", "KEEPTTL", "PX", "10"],
+			["SET", "k", "v", "PX", "10", "KEEPTTL"],
+		] {
+			assert_eq!(session.run(args), b"-ERR syntax error\r\n", "{:?}", args);
+		}
+		assert_eq!(session.run(&["EXISTS", "k"]), b":0\r\n");
+	}
+
+	#[test]
+	fn incr_refuses_non_canonical_integers() {
+		let mut session = Session::new();
+		for value in &["012", "+1", "-0", " 1", "1.0", "9223372036854775808"] {
+			session.run(&["SET", "k", value]);
+			assert_eq!(
+				session.run(&["INCR", "k"]),
+				&b"-ERR value is not an integer or out of range\r\n"[..],
+				"{:?}",
+				value
+			);
+		}
+		session.run(&["SET", "k", "-5"]);
+		assert_eq!(session.run(&["INCR", "k"]), b":-4\r\n");
+	}
+}
@@ -0,0 +1,124 @@
+// Not every test module uses every helper.
+#![allow(dead_code)]
+
+use super::server::handle_message;
+use super::types::*;
+use bytes::BytesMut;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+/// One connection to an in-memory server, for driving commands through
+/// `handle_message` and checking the exact reply bytes.
+pub struct Session {
+	pub state: State,
+	pub client: Client,
+	buf: BytesMut,
+}
+
+impl Session {
+	pub fn new() -> Session {
+		Session::with_state(ServerState::new())
+	}
+
+	/// A session on a server configured by the caller.
+	pub fn with_state(state: ServerState) -> Session {
+		Session::on(Arc::new(state))
+	}
+
+	/// Another connection to the same server as `self`.
+	pub fn join(&self) -> Session {
+		Session::on(Arc::clone(&self.state))
+	}
+
+	fn on(state: State) -> Session {
+		let client = state.register_client("127.0.0.1:6380".to_string(), Box::new(|| {}));
+		Session {
+			state,
+			client,
+			buf: BytesMut::new(),
+		}
+	}
+
+	/// Feed raw bytes in as if read from the socket, returning everything
+	/// written back. An incomplete trailing command is kept for the next
+	/// call.
+	pub fn send(&mut self, input: &[u8]) -> Vec<u8> {
+		let mut out = Vec::new();
+		self.buf.extend_from_slice(input);
+		handle_message(&mut out, &mut self.buf, &mut self.state, &mut self.client).unwrap();
+		out
+	}
+
+	/// Run one command given as arguments, returning its reply.
+	pub fn run(&mut self, args: &[&str]) -> Vec<u8> {
+		let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+		self.run_bytes(&args)
+	}
+
+	pub fn run_bytes(&mut self, args: &[&[u8]]) -> Vec<u8> {
+		self.send(&request(args))
+	}
+}
+
+/// Encode `args` as a RESP array of bulk strings.
+pub fn request(args: &[&[u8]]) -> Vec<u8> {
+	let mut out = format!("*{}\r\n", args.len()).into_bytes();
+	for arg in args {
+		out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+		out.extend_from_slice(arg);
+		out.extend_from_slice(b"\r\n");
+	}
+	out
+}
+
+/// A scripted connection: each read returns the next chunk of `input`
+/// (then EOF), and each write takes at most `max_write` bytes.
+pub struct MockStream {
+	input: VecDeque<Vec<u8>>,
+	pub output: Vec<u8>,
+	pub max_write: usize,
+	pub reads: usize,
+	pub writes: usize,
+}
+
+impl MockStream {
+	pub fn new(input: Vec<Vec<u8>>) -> MockStream {
+		MockStream {
+			input: input.into(),
+			output: Vec::new(),
+			max_write: usize::MAX,
+			reads: 0,
+			writes: 0,
+		}
+	}
+}
+
+impl Read for MockStream {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut chunk = match self.input.pop_front() {
+			Some(chunk) => chunk,
+			None => return Ok(0),
+		};
+		let n = chunk.len().min(buf.len());
+		buf[..n].copy_from_slice(&chunk[..n]);
+		if n < chunk.len() {
+			self.input.push_front(chunk.split_off(n));
+		}
+		self.reads += 1;
+		Ok(n)
+	}
+}
+
+impl Write for MockStream {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = buf.len().min(self.max_write);
+		self.output.extend_from_slice(&buf[..n]);
+		self.writes += 1;
+		Ok(n)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
@@ -1,12 +1,275 @@
+use super::acl::acl;
+use super::commands::{command, lookup_command};
+use super::db::Db;
+use super::debug::debug;
+use super::info::info;
+use super::keys::{
+	dbsize, del, exists, expire, expireat, expiretime, key_type, pexpire, pexpireat, pexpiretime,
+	pttl, ttl,
+};
+use super::memory::memory;
+use super::object::object;
+use super::random::random_u64;
+use super::replication::{failover, generate_id, replicaof};
+use super::slowlog::{
+	slowlog, SlowLog, DEFAULT_SLOWLOG_LOG_SLOWER_THAN, DEFAULT_SLOWLOG_MAX_LEN,
+};
+use super::stream::{xadd, xlen, xrange, xrevrange};
+use super::strings::{
+	append, bitfield, bitfield_ro, bitpos, decr, decrby, getdel, getex, getrange, incr, incrby, lcs,
+	mget, mset, psetex, set, setex, setrange,
+};
 use bytes::{Bytes, BytesMut};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
-use std::sync::{Arc, Mutex};
+use std::str;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
 pub const MESSAGE_SIZE: usize = 1024;
+/// Redis' default 512MB limit on a single string value.
+pub const DEFAULT_PROTO_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// How deeply arrays may nest in a request. Commands only need one level;
+/// the limit keeps a hostile payload from exhausting the parser's stack.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 128;
+/// Longest line, such as a simple string or a length header, read while
+/// waiting for its `\r\n`. Fixed in Redis too; without it a client could
+/// make the server buffer without bound.
+pub const PROTO_INLINE_MAX_SIZE: usize = 64 * 1024;
+/// Most elements an array header may announce, as in Redis.
+pub const PROTO_MAX_MULTIBULK_LEN: i64 = i32::MAX as i64;
+/// Most elements reserved up front for an array, so a huge header can't
+/// allocate before any of its elements have arrived.
+pub const ARRAY_PREALLOC_LIMIT: usize = 1024;
+/// How often the background sweeper runs, as Redis' default `hz` of 10.
+pub const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+/// Keys with a TTL checked per round of a sweep.
+const ACTIVE_EXPIRE_KEYS_PER_ROUND: usize = 20;
+/// Longest a sweep holds the keyspace lock.
+const ACTIVE_EXPIRE_BUDGET: Duration = Duration::from_millis(25);
 
-pub type State = Arc<Mutex<HashMap<String, String>>>;
+pub type State = Arc<ServerState>;
+
+/// Everything shared between connections.
+pub struct ServerState {
+	/// The keyspace.
+	pub db: Mutex<Db>,
+	/// Per-command call counts and timings, keyed by command table name.
+	pub command_stats: Mutex<HashMap<&'static str, CommandStats>>,
+	pub started_at: Instant,
+	/// Identifies this server process; fixed for its lifetime.
+	pub run_id: String,
+	/// The replication history id, reset by `DEBUG CHANGE-REPL-ID`.
+	pub master_replid: Mutex<String>,
+	/// Largest string value a command may create, in bytes.
+	pub proto_max_bulk_len: usize,
+	/// Deepest array nesting accepted in a request.
+	pub max_nesting_depth: usize,
+	/// Refuse commands flagged `write`, for serving a snapshot safely.
+	pub read_only: bool,
+	/// Commands slower than `slowlog_log_slower_than` microseconds.
+	pub slowlog: Mutex<SlowLog>,
+	/// Threshold for the slow log in microseconds; negative disables it.
+	pub slowlog_log_slower_than: i64,
+	pub slowlog_max_len: usize,
+	/// Up to how many percent longer `SET EX`/`PX`, `SETEX` and `PSETEX`
+	/// make a TTL, chosen at random per key; 0 applies TTLs exactly. Other
+	/// commands that set a TTL, such as `EXPIRE` and `GETEX`, never jitter.
+	pub expire_jitter: u64,
+	/// Whether the background sweeper removes expired keys; toggled by
+	/// `DEBUG SET-ACTIVE-EXPIRE`. Lazy expiry on access applies regardless.
+	pub active_expire: AtomicBool,
+	/// Every open connection, by client id.
+	pub clients: Mutex<HashMap<u64, ClientHandle>>,
+	next_client_id: AtomicU64,
+	/// Set by `CLIENT PAUSE`, and signalled when it is lifted early.
+	pause: Mutex<Option<ClientPause>>,
+	pause_lifted: Condvar,
+}
+
+/// A `CLIENT PAUSE` in effect.
+#[derive(Clone, Copy)]
+pub struct ClientPause {
+	pub until: Instant,
+	/// `WRITE` mode: only commands flagged `write` wait.
+	pub writes_only: bool,
+}
+
+impl ServerState {
+	pub fn new() -> ServerState {
+		ServerState {
+			db: Mutex::new(Db::new()),
+			command_stats: Mutex::new(HashMap::new()),
+			started_at: Instant::now(),
+			run_id: generate_id(),
+			master_replid: Mutex::new(generate_id()),
+			proto_max_bulk_len: DEFAULT_PROTO_MAX_BULK_LEN,
+			max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+			read_only: false,
+			slowlog: Mutex::new(SlowLog::new()),
+			slowlog_log_slower_than: DEFAULT_SLOWLOG_LOG_SLOWER_THAN,
+			slowlog_max_len: DEFAULT_SLOWLOG_MAX_LEN,
+			expire_jitter: 0,
+			active_expire: AtomicBool::new(true),
+			clients: Mutex::new(HashMap::new()),
+			next_client_id: AtomicU64::new(1),
+			pause: Mutex::new(None),
+			pause_lifted: Condvar::new(),
+		}
+	}
+
+	/// Assign a new connection its id and make it visible to other
+	/// connections. `shutdown` must unblock the connection's pending read.
+	pub fn register_client(&self, addr: String, shutdown: Box<dyn Fn() + Send>) -> Client {
+		let id = self.next_client_id.fetch_add(1, Ordering::Relaxed);
+		let killed = Arc::new(AtomicBool::new(false));
+		self.clients.lock().unwrap().insert(
+			id,
+			ClientHandle {
+				addr: addr.clone(),
+				name: Bytes::new(),
+				connected_at: Instant::now(),
+				killed: Arc::clone(&killed),
+				shutdown,
+			},
+		);
+		Client {
+			id,
+			addr,
+			no_evict: false,
+			no_touch: false,
+			killed,
+		}
+	}
+
+	pub fn unregister_client(&self, id: u64) {
+		self.clients.lock().unwrap().remove(&id);
+	}
+
+	/// Hold commands back until `until`. Overlapping pauses combine into
+	/// the longer and more restrictive of the two, as in Redis.
+	pub fn pause_clients(&self, until: Instant, writes_only: bool) {
+		let mut pause = self.pause.lock().unwrap();
+		*pause = Some(match *pause {
+			Some(current) if current.until > Instant::now() => ClientPause {
+				until: current.until.max(until),
+				writes_only: current.writes_only && writes_only,
+			},
+			_ => ClientPause { until, writes_only },
+		});
+	}
+
+	pub fn unpause_clients(&self) {
+		*self.pause.lock().unwrap() = None;
+		self.pause_lifted.notify_all();
+	}
+
+	/// Block until no pause applies to a command, `is_write` saying
+	/// whether it is flagged `write`.
+	pub fn wait_while_paused(&self, is_write: bool) {
+		let mut pause = self.pause.lock().unwrap();
+		while let Some(current) = *pause {
+			let now = Instant::now();
+			if (current.writes_only && !is_write) || current.until <= now {
+				break;
+			}
+			pause = self.pause_lifted.wait_timeout(pause, current.until - now).unwrap().0;
+		}
+	}
+
+	/// `ms` lengthened by `expire_jitter`, so keys given the same TTL at the
+	/// same time don't all expire together. Only called when a key is
+	/// written with its TTL; changing the TTL of an existing key is exact.
+	pub fn jitter_ttl(&self, ms: u64) -> u64 {
+		if self.expire_jitter == 0 {
+			return ms;
+		}
+		let spread = (ms as u128 * self.expire_jitter as u128 / 100) as u64;
+		ms.saturating_add(random_u64() % spread.saturating_add(1))
+	}
+
+	/// One run of the background sweeper. Like Redis, it keeps sampling
+	/// while more than a quarter of a round had expired, within a time
+	/// budget so clients aren't held up for long.
+	pub fn active_expire_cycle(&self) {
+		if !self.active_expire.load(Ordering::Relaxed) {
+			return;
+		}
+		let started = Instant::now();
+		let mut db = self.db.lock().unwrap();
+		while db.active_expire(ACTIVE_EXPIRE_KEYS_PER_ROUND) * 4 > ACTIVE_EXPIRE_KEYS_PER_ROUND
+			&& started.elapsed() < ACTIVE_EXPIRE_BUDGET
+		{}
+	}
+
+	pub fn record_command(&self, name: &'static str, elapsed: Duration) {
+		let mut stats = self.command_stats.lock().unwrap();
+		let entry = stats.entry(name).or_default();
+		entry.calls += 1;
+		entry.usec += elapsed.as_micros() as u64;
+	}
+}
+
+impl Default for ServerState {
+	fn default() -> ServerState {
+		ServerState::new()
+	}
+}
+
+/// Per-connection state, owned by the connection's thread.
+pub struct Client {
+	pub id: u64,
+	pub addr: String,
+	/// Set by `CLIENT NO-EVICT`. There is no eviction yet, so nothing
+	/// reads it.
+	#[allow(dead_code)]
+	pub no_evict: bool,
+	/// Set by `CLIENT NO-TOUCH`: this connection's commands leave the
+	/// last-access time of their keys alone, except for `TOUCH`.
+	pub no_touch: bool,
+	killed: Arc<AtomicBool>,
+}
+
+impl Client {
+	/// Ask for this connection to be closed once its pending replies are
+	/// written.
+	pub fn close(&self) {
+		self.killed.store(true, Ordering::Relaxed);
+	}
+
+	/// Whether another connection (or this one) has asked for this
+	/// connection to be closed.
+	pub fn is_killed(&self) -> bool {
+		self.killed.load(Ordering::Relaxed)
+	}
+}
+
+/// What other connections can see of, and do to, a connection.
+pub struct ClientHandle {
+	pub addr: String,
+	/// Set by `CLIENT SETNAME`; empty until then.
+	pub name: Bytes,
+	pub connected_at: Instant,
+	killed: Arc<AtomicBool>,
+	shutdown: Box<dyn Fn() + Send>,
+}
+
+impl ClientHandle {
+	/// Flag the connection for closing and wake it if it's blocked on a
+	/// read, so its loop notices.
+	pub fn kill(&self) {
+		self.killed.store(true, Ordering::Relaxed);
+		(self.shutdown)();
+	}
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct CommandStats {
+	pub calls: u64,
+	pub usec: u64,
+}
 
 /// RedisValue is the canonical type for values flowing
 /// through the system. Inputs are converted into RedisValues,
@@ -93,11 +356,32 @@ pub enum RESPError {
 	NullBulkString,
 	BadBulkStringSize,
 	BadArraySize(usize),
+	/// Arrays nested deeper than the allowed depth.
+	TooDeeplyNested,
+	/// A line longer than `PROTO_INLINE_MAX_SIZE`.
+	TooBigLine,
 }
 
 #[derive(Debug)]
 pub enum ReturnError {
 	UnknownType,
+	/// The key holds a different type than the command works on.
+	WrongType,
+}
+
+impl RESPError {
+	/// The detail sent to the client in a `Protocol error` reply.
+	pub fn protocol_message(&self) -> &'static str {
+		match self {
+			RESPError::UnexpectedEnd => "unexpected end of request",
+			RESPError::UnknownStartingByte => "unknown type byte",
+			RESPError::IntParseFailure => "invalid integer",
+			RESPError::NullBulkString | RESPError::BadBulkStringSize => "invalid bulk length",
+			RESPError::BadArraySize(_) => "invalid multibulk length",
+			RESPError::TooDeeplyNested => "too many nested arrays",
+			RESPError::TooBigLine => "too big inline request",
+		}
+	}
 }
 
 impl fmt::Display for RESPError {
@@ -109,94 +393,454 @@ impl fmt::Display for RESPError {
 			RESPError::NullBulkString => write!(f, "NullBulkString"),
 			RESPError::BadBulkStringSize => write!(f, "BadBulkStringSize"),
 			RESPError::BadArraySize(size) => write!(f, "BadBulkStringSize {}", size),
+			RESPError::TooDeeplyNested => write!(f, "TooDeeplyNested"),
+			RESPError::TooBigLine => write!(f, "TooBigLine"),
 		}
 	}
 }
 
+/// Write `bytes` the way redis-cli prints a bulk string: quoted, with
+/// quotes, backslashes and unprintable bytes escaped.
+fn write_quoted(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+	f.write_str("\"")?;
+	for &b in bytes {
+		match b {
+			b'\\' => f.write_str("\\\\")?,
+			b'"' => f.write_str("\\\"")?,
+			b'\n' => f.write_str("\\n")?,
+			b'\r' => f.write_str("\\r")?,
+			b'\t' => f.write_str("\\t")?,
+			0x07 => f.write_str("\\a")?,
+			0x08 => f.write_str("\\b")?,
+			0x20..=0x7e => write!(f, "{}", b as char)?,
+			_ => write!(f, "\\x{:02x}", b)?,
+		}
+	}
+	f.write_str("\"")
+}
+
+/// Write `items` as a redis-cli numbered list. Lines after the first are
+/// indented by `indent` columns so nested lists line up under their parent.
+fn write_list<T>(
+	f: &mut fmt::Formatter<'_>,
+	items: &[T],
+	indent: usize,
+	write_item: impl Fn(&T, &mut fmt::Formatter<'_>, usize) -> fmt::Result,
+) -> fmt::Result {
+	if items.is_empty() {
+		return f.write_str("(empty array)");
+	}
+	let width = items.len().to_string().len();
+	for (i, item) in items.iter().enumerate() {
+		if i > 0 {
+			write!(f, "\n{:indent$}", "", indent = indent)?;
+		}
+		write!(f, "{:>width$}) ", i + 1, width = width)?;
+		write_item(item, f, indent + width + 2)?;
+	}
+	Ok(())
+}
+
+impl RedisValue {
+	fn write_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+		match self {
+			RedisValue::String(s) => write_quoted(f, s),
+			RedisValue::Error(e) => write!(f, "(error) {}", String::from_utf8_lossy(e)),
+			RedisValue::ErrorMsg(e) => write!(f, "(error) {}", String::from_utf8_lossy(e)),
+			RedisValue::Int(n) => write!(f, "(integer) {}", n),
+			RedisValue::Array(values) => write_list(f, values, indent, RedisValue::write_indented),
+			RedisValue::NullArray | RedisValue::NullBulkString => f.write_str("(nil)"),
+		}
+	}
+}
+
+/// Renders like redis-cli, e.g. `1) "GET"` / `2) "key"` for a command.
+impl fmt::Display for RedisValue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.write_indented(f, 0)
+	}
+}
+
+impl fmt::Debug for RedisValue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Display::fmt(self, f)
+	}
+}
+
 #[allow(dead_code)]
 pub enum ReturnValue {
 	Ok,
+	/// A status reply such as `+PONG`.
+	SimpleString(Bytes),
+	/// A bulk string reply.
 	StringRes(Bytes),
 	MultiStringRes(Vec<Bytes>),
 	Array(Vec<ReturnValue>),
 	IntRes(i64),
 	Nil,
+	/// An error reply, without the leading `-` or trailing CRLF.
+	Error(Bytes),
+}
+
+impl ReturnValue {
+	fn write_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+		match self {
+			ReturnValue::Ok => f.write_str("OK"),
+			ReturnValue::SimpleString(s) => f.write_str(&String::from_utf8_lossy(s)),
+			ReturnValue::StringRes(s) => write_quoted(f, s),
+			ReturnValue::MultiStringRes(strings) => {
+				write_list(f, strings, indent, |s, f, _| write_quoted(f, s))
+			}
+			ReturnValue::Array(values) => write_list(f, values, indent, ReturnValue::write_indented),
+			ReturnValue::IntRes(n) => write!(f, "(integer) {}", n),
+			ReturnValue::Nil => f.write_str("(nil)"),
+			ReturnValue::Error(e) => write!(f, "(error) {}", String::from_utf8_lossy(e)),
+		}
+	}
+}
+
+/// Renders like redis-cli would print the reply.
+impl fmt::Display for ReturnValue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.write_indented(f, 0)
+	}
+}
+
+impl fmt::Debug for ReturnValue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Display::fmt(self, f)
+	}
 }
 
 impl ReturnValue {
 	pub fn handle_string(b: Bytes) -> Result<ReturnValue, ReturnError> {
 		let redis_string = String::from_utf8_lossy(&b);
 		match redis_string.to_string().to_lowercase().as_str() {
-			"echo" => Ok(ReturnValue::StringRes(write_simple_string(b))),
-			"ping" => Ok(ReturnValue::StringRes(write_simple_string(Bytes::from(
-				"PONG",
-			)))),
+			"echo" => Ok(ReturnValue::SimpleString(b)),
+			"ping" => Ok(ReturnValue::SimpleString(Bytes::from("PONG"))),
 			_ => Err(ReturnError::UnknownType),
 		}
 	}
 
-	pub fn handle_array(a: Vec<RedisValue>, state: &mut State) -> Result<ReturnValue, ReturnError> {
-		let head = Bytes::try_from(a[0].clone())?;
+	pub fn handle_array(
+		a: &[RedisValue],
+		state: &mut State,
+		client: &mut Client,
+	) -> Result<ReturnValue, ReturnError> {
+		let head = match a.first() {
+			Some(head) => Bytes::try_from(head.clone())?,
+			None => return Ok(unknown_command(b"")),
+		};
 		let head_s = String::from_utf8_lossy(&head);
-		match head_s.to_string().to_lowercase().as_str() {
+		let spec = lookup_command(&head_s);
+		// Checked here from the command table, so handlers can index their
+		// arguments without checking the count again.
+		if let Some(spec) = spec {
+			if !spec.accepts_arity(a.len()) {
+				return Ok(wrong_number_of_arguments(spec.name));
+			}
+			if state.read_only && spec.is_write() {
+				return Ok(ReturnValue::Error(Bytes::from(
+					"READONLY You can't write against a read only replica.",
+				)));
+			}
+			// CLIENT itself never waits, so a pause can always be lifted.
+			if spec.name != "client" {
+				state.wait_while_paused(spec.is_write());
+			}
+		}
+		let reply = match head_s.to_string().to_lowercase().as_str() {
 			"echo" => {
 				let response = Bytes::try_from(a[1].clone())?;
-				Ok(ReturnValue::StringRes(write_bulk_string(response)))
-			}
-			"ping" => Ok(ReturnValue::StringRes(write_simple_string(Bytes::from(
-				"PONG",
-			)))),
-			"set" => {
-				let key = Bytes::try_from(a[1].clone())?;
-				let key_s = String::from_utf8_lossy(&key).to_string();
-				let value = Bytes::try_from(a[1].clone())?;
-				let value_s = String::from_utf8_lossy(&value).to_string();
-
-				match state.lock().unwrap().insert(key_s, value_s) {
-					Some(old_value) => Ok(ReturnValue::StringRes(write_bulk_string(Bytes::from(
-						old_value,
-					)))),
-					None => Ok(ReturnValue::Ok),
-				}
+				Ok(ReturnValue::StringRes(response))
 			}
+			"ping" => Ok(ReturnValue::SimpleString(Bytes::from("PONG"))),
+			"command" => command(&a[1..]),
+			"info" => info(&a[1..], state),
+			"acl" => acl(a),
+			"client" => super::client::client(a, state, client),
+			"debug" => debug(a, state),
+			"memory" => memory(a, state),
+			"object" => object(a, state),
+			"slowlog" => slowlog(a, state),
+			// Cluster-mode clients may send these on connect; a standalone
+			// server has nothing to change, so they are accepted as no-ops.
+			"asking" | "readonly" | "readwrite" => Ok(ReturnValue::Ok),
+			"replicaof" | "slaveof" => replicaof(a),
+			"failover" => failover(a),
+			"set" => set(a, state),
+			"setex" => setex(a, state),
+			"psetex" => psetex(a, state),
 			"get" => {
 				let key = Bytes::try_from(a[1].clone())?;
-				let key_s = String::from_utf8_lossy(&key).to_string();
 
-				match state.lock().unwrap().get(&key_s) {
-					Some(value) => Ok(ReturnValue::StringRes(write_bulk_string(Bytes::from(
-						value.to_string(),
-					)))),
+				match state.db.lock().unwrap().get(&key)? {
+					Some(value) => Ok(ReturnValue::StringRes(value)),
 					None => Ok(ReturnValue::Nil),
 				}
 			}
+			"del" | "unlink" => del(a, state),
+			"exists" | "touch" => exists(a, state),
+			"expire" => expire(a, state),
+			"pexpire" => pexpire(a, state),
+			"expireat" => expireat(a, state),
+			"pexpireat" => pexpireat(a, state),
+			"expiretime" => expiretime(a, state),
+			"pexpiretime" => pexpiretime(a, state),
+			"ttl" => ttl(a, state),
+			"pttl" => pttl(a, state),
+			"type" => key_type(a, state),
+			"dbsize" => dbsize(a, state),
+			"append" => append(a, state),
+			"bitfield" => bitfield(a, state),
+			"bitfield_ro" => bitfield_ro(a, state),
+			"bitpos" => bitpos(a, state),
+			"getdel" => getdel(a, state),
+			"getex" => getex(a, state),
+			"mget" => mget(a, state),
+			"mset" => mset(a, state),
+			"getrange" => getrange(a, state),
+			"incr" => incr(a, state),
+			"decr" => decr(a, state),
+			"incrby" => incrby(a, state),
+			"decrby" => decrby(a, state),
+			"lcs" => lcs(a, state),
+			"setrange" => setrange(a, state),
+			"xadd" => xadd(a, state),
+			"xlen" => xlen(a, state),
+			"xrange" => xrange(a, state),
+			"xrevrange" => xrevrange(a, state),
 			_ => Err(ReturnError::UnknownType),
+		};
+		// As in Redis, `TOUCH` updates the access time even for a
+		// connection in NO-TOUCH mode.
+		if let Some(spec) = spec {
+			if !client.no_touch || spec.name == "touch" {
+				let keys = spec.keys(a);
+				if !keys.is_empty() {
+					let mut db = state.db.lock().unwrap();
+					for key in keys {
+						if let RedisValue::String(key) = key {
+							db.touch(key);
+						}
+					}
+				}
+			}
 		}
+		reply
 	}
 
 	pub fn parse_redis_value(
-		value: RedisValue,
+		value: &RedisValue,
 		state: &mut State,
+		client: &mut Client,
 	) -> Result<ReturnValue, ReturnError> {
 		match value {
-			RedisValue::String(cmd) => ReturnValue::handle_string(cmd),
-			RedisValue::Array(cmd) => ReturnValue::handle_array(cmd, state),
-			_ => unimplemented!(),
+			RedisValue::String(cmd) => ReturnValue::handle_string(cmd.clone()),
+			RedisValue::Array(cmd) => ReturnValue::handle_array(cmd, state, client),
+			_ => Ok(ReturnValue::Error(Bytes::from(
+				"ERR Protocol error: expected array",
+			))),
 		}
 	}
 }
 
-pub fn write_simple_string(b: Bytes) -> Bytes {
-	let s = String::from_utf8_lossy(&b);
-	Bytes::from(format!("+{}\r\n", s))
+pub fn wrong_number_of_arguments(cmd: &str) -> ReturnValue {
+	ReturnValue::Error(Bytes::from(format!(
+		"ERR wrong number of arguments for '{}' command",
+		cmd
+	)))
+}
+
+pub fn unknown_command(name: &[u8]) -> ReturnValue {
+	ReturnValue::Error(Bytes::from(format!(
+		"ERR unknown command '{}'",
+		String::from_utf8_lossy(name)
+	)))
 }
 
-pub fn write_bulk_string(b: Bytes) -> Bytes {
-	let s = String::from_utf8_lossy(&b);
-	let size = s.len();
-	Bytes::from(format!("${}\r\n{}\r\n", size, s))
+pub fn wrong_type() -> ReturnValue {
+	ReturnValue::Error(Bytes::from(
+		"WRONGTYPE Operation against a key holding the wrong kind of value",
+	))
 }
 
-pub fn write_bulk_string_nil() -> Bytes {
-	Bytes::from(format!("$-1\r\n"))
+/// Parse an argument as a base-10 signed integer, as Redis does for
+/// offsets, counts and indices.
+pub fn integer_arg(value: &RedisValue) -> Option<i64> {
+	match value {
+		RedisValue::String(b) => str::from_utf8(b).ok()?.parse::<i64>().ok(),
+		_ => None,
+	}
+}
+
+/// The `<COMMAND> HELP` reply for a container command: `lines` describes
+/// each subcommand, with HELP itself appended.
+pub fn subcommand_help(command: &str, lines: &[&str]) -> ReturnValue {
+	let mut help = Vec::with_capacity(lines.len() + 3);
+	help.push(format!(
+		"{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+		command
+	));
+	help.extend(lines.iter().map(|line| line.to_string()));
+	help.push("HELP".to_string());
+	help.push("    Print this help.".to_string());
+	ReturnValue::Array(
+		help.into_iter()
+			.map(|line| ReturnValue::SimpleString(Bytes::from(line)))
+			.collect(),
+	)
+}
+
+pub fn unknown_subcommand(command: &str, subcommand: &[u8]) -> ReturnValue {
+	ReturnValue::Error(Bytes::from(format!(
+		"ERR unknown subcommand '{}'. Try {} HELP.",
+		String::from_utf8_lossy(subcommand),
+		command
+	)))
+}
+
+pub fn invalid_expire_time(cmd: &str) -> ReturnValue {
+	ReturnValue::Error(Bytes::from(format!(
+		"ERR invalid expire time in '{}' command",
+		cmd
+	)))
+}
+
+pub fn syntax_error() -> ReturnValue {
+	ReturnValue::Error(Bytes::from("ERR syntax error"))
+}
+
+pub fn not_an_integer() -> ReturnValue {
+	ReturnValue::Error(Bytes::from("ERR value is not an integer or out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{RedisValue, ReturnValue, ServerState};
+	use crate::testing::Session;
+	use bytes::Bytes;
+
+	#[test]
+	fn cluster_commands_are_no_ops() {
+		let mut session = Session::new();
+		for cmd in &["ASKING", "READONLY", "READWRITE"] {
+			assert_eq!(session.run(&[cmd]), b"+OK\r\n", "{}", cmd);
+		}
+	}
+
+	#[test]
+	fn nested_arrays_display_like_redis_cli() {
+		let value = RedisValue::Array(vec![
+			RedisValue::String(Bytes::from("a")),
+			RedisValue::Array(vec![
+				RedisValue::Int(1),
+				RedisValue::String(Bytes::from("b\n\"\x01")),
+			]),
+			RedisValue::Array(vec![]),
+			RedisValue::NullBulkString,
+		]);
+		let expected = "1) \"a\"\n\
+		                2) 1) (integer) 1\n   2) \"b\\n\\\"\\x01\"\n\
+		                3) (empty array)\n\
+		                4) (nil)";
+		assert_eq!(value.to_string(), expected);
+		assert_eq!(format!("{:?}", value), expected);
+	}
+
+	#[test]
+	fn replies_display_like_redis_cli() {
+		let items = (1..=10).map(ReturnValue::IntRes).collect();
+		let reply = ReturnValue::Array(vec![
+			ReturnValue::Ok,
+			ReturnValue::Array(items),
+			ReturnValue::MultiStringRes(vec![Bytes::from("x")]),
+			ReturnValue::Error(Bytes::from("ERR nope")),
+		]);
+		let mut expected = String::from("1) OK\n2)  1) (integer) 1\n");
+		for i in 2..=10 {
+			expected.push_str(&format!("   {:>2}) (integer) {}\n", i, i));
+		}
+		expected.push_str("3) 1) \"x\"\n4) (error) ERR nope");
+		assert_eq!(reply.to_string(), expected);
+		assert_eq!(format!("{:?}", reply), expected);
+	}
+
+	#[test]
+	fn read_only_mode_refuses_writes() {
+		let mut state = ServerState::new();
+		state.read_only = true;
+		let mut session = Session::with_state(state);
+		let read_only = &b"-READONLY You can't write against a read only replica.\r\n"[..];
+		assert_eq!(session.run(&["SET", "k", "v"]), read_only);
+		assert_eq!(session.run(&["INCR", "n"]), read_only);
+		assert_eq!(session.run(&["GET", "k"]), b"$-1\r\n");
+		assert_eq!(session.run(&["DBSIZE"]), b":0\r\n");
+		assert_eq!(session.run(&["PING"]), b"+PONG\r\n");
+	}
+
+	#[test]
+	fn empty_requests_get_an_error_reply() {
+		let mut session = Session::new();
+		assert_eq!(session.send(b"*0\r\n*0\r\n"), b"-ERR unknown command ''\r\n".repeat(2));
+		assert_eq!(session.run(&["PING"]), b"+PONG\r\n");
+	}
+
+	#[test]
+	fn arity_is_checked_before_dispatch() {
+		let mut session = Session::new();
+		let wrong_arity = |cmd: &str| {
+			format!("-ERR wrong number of arguments for '{}' command\r\n", cmd).into_bytes()
+		};
+		assert_eq!(session.run(&["GET"]), wrong_arity("get"));
+		assert_eq!(session.run(&["GET", "a", "b"]), wrong_arity("get"));
+		assert_eq!(session.run(&["MGET"]), wrong_arity("mget"));
+		assert_eq!(session.run(&["mget"]), wrong_arity("mget"));
+		assert_eq!(session.run(&["DEL"]), wrong_arity("del"));
+		assert_eq!(session.run(&["INCRBY", "k"]), wrong_arity("incrby"));
+		assert_eq!(session.run(&["BITFIELD_RO"]), wrong_arity("bitfield_ro"));
+		assert_eq!(session.run(&["DBSIZE", "x"]), wrong_arity("dbsize"));
+		assert_eq!(session.run(&["CLIENT"]), wrong_arity("client"));
+		assert_eq!(session.run(&["XRANGE", "s", "-"]), wrong_arity("xrange"));
+		assert_eq!(session.run(&["REPLICAOF", "NO"]), wrong_arity("replicaof"));
+		assert_eq!(session.run(&["MEMORY", "USAGE"]), wrong_arity("memory|usage"));
+		// Rules the table can't express are still checked by the handler.
+		assert_eq!(session.run(&["XRANGE", "s", "-", "+", "COUNT"]), b"-ERR syntax error\r\n");
+		assert_eq!(session.run(&["MGET", "a", "b"]), b"*2\r\n$-1\r\n$-1\r\n");
+	}
+
+	#[test]
+	fn jitter_spreads_deadlines_within_the_bound() {
+		let mut state = ServerState::new();
+		state.expire_jitter = 50;
+		let mut session = Session::with_state(state);
+		let mut jitters = Vec::new();
+		for key in &["a", "b", "c", "d"] {
+			let before = crate::db::now_ms();
+			session.run(&["SET", key, "v", "EX", "1000"]);
+			let after = crate::db::now_ms();
+			let reply = session.run(&["PEXPIRETIME", key]);
+			let deadline: u64 =
+				String::from_utf8_lossy(&reply[1..reply.len() - 2]).parse().unwrap();
+			assert!(deadline >= before + 1_000_000 && deadline <= after + 1_500_000);
+			jitters.push(deadline - before - 1_000_000);
+		}
+		// Equal TTLs would differ only by the clock moving on between calls.
+		let spread = jitters.iter().max().unwrap() - jitters.iter().min().unwrap();
+		assert!(spread > 1000, "{:?}", jitters);
+	}
+
+	#[test]
+	fn jitter_leaves_expire_and_getex_exact() {
+		let mut state = ServerState::new();
+		state.expire_jitter = 100;
+		let mut session = Session::with_state(state);
+		session.run(&["MSET", "a", "v", "b", "v", "c", "v"]);
+		session.run(&["EXPIRE", "a", "1000"]);
+		session.run(&["PEXPIRE", "b", "1000000"]);
+		session.run(&["GETEX", "c", "EX", "1000"]);
+		for key in &["a", "b", "c"] {
+			assert_eq!(session.run(&["TTL", key]), b":1000\r\n", "{}", key);
+		}
+	}
 }